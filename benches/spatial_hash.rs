@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+use bevy_jam_2::spatial_hash::SpatialHash;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// People per square unit, kept the same for every crowd size.
+const DENSITY: f32 = 0.05;
+const NEIGHBOUR_DISTANCE: f32 = 15.0;
+const MAX_NEIGHBOURS: usize = 10;
+/// Largest crowd the quadratic brute force is run on.
+const MAX_BRUTE_FORCE_AGENTS: usize = 1_000;
+
+fn agent_positions(count: usize) -> Vec<Vec2> {
+    let side = (count as f32 / DENSITY).sqrt();
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    (0..count)
+        .map(|_| Vec2::new(rng.gen_range(0.0..side), rng.gen_range(0.0..side)))
+        .collect()
+}
+
+/// Rebuilds the hash and finds the nearest neighbours of every agent, as movement does each frame.
+fn spatial_hash_frame(spatial_hash: &mut SpatialHash, positions: &[Vec2]) -> usize {
+    spatial_hash.clear();
+    for (index, &pos) in positions.iter().enumerate() {
+        spatial_hash.insert(Entity::from_raw(index as u32), pos, Vec2::ZERO);
+    }
+    positions
+        .iter()
+        .enumerate()
+        .map(|(index, &pos)| {
+            spatial_hash
+                .k_nearest(
+                    pos,
+                    MAX_NEIGHBOURS,
+                    NEIGHBOUR_DISTANCE,
+                    Some(Entity::from_raw(index as u32)),
+                )
+                .len()
+        })
+        .sum()
+}
+
+fn brute_force_frame(positions: &[Vec2]) -> usize {
+    positions
+        .iter()
+        .enumerate()
+        .map(|(index, &pos)| {
+            let mut nearest: Vec<_> = positions
+                .iter()
+                .enumerate()
+                .filter(|&(other_index, other_pos)| {
+                    other_index != index && other_pos.distance(pos) <= NEIGHBOUR_DISTANCE
+                })
+                .map(|(_, other_pos)| other_pos.distance_squared(pos))
+                .collect();
+            nearest.sort_by(|a, b| a.total_cmp(b));
+            nearest.truncate(MAX_NEIGHBOURS);
+            nearest.len()
+        })
+        .sum()
+}
+
+fn neighbour_queries(c: &mut Criterion) {
+    let mut group = c.benchmark_group("neighbour_queries");
+    group.sample_size(10);
+    for count in [100, 1_000, 10_000] {
+        let positions = agent_positions(count);
+        group.bench_with_input(
+            BenchmarkId::new("spatial_hash", count),
+            &positions,
+            |b, positions| {
+                let mut spatial_hash = SpatialHash::default();
+                b.iter(|| spatial_hash_frame(&mut spatial_hash, positions));
+            },
+        );
+        if count <= MAX_BRUTE_FORCE_AGENTS {
+            group.bench_with_input(
+                BenchmarkId::new("brute_force", count),
+                &positions,
+                |b, positions| b.iter(|| brute_force_frame(positions)),
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, neighbour_queries);
+criterion_main!(benches);
@@ -0,0 +1,144 @@
+//! Live crowd density on a coarse grid: every person adds to the cell it stands in, the pathfinder
+//! reads the density as an extra cost, and people whose path runs into a congested place
+//! periodically look for another one, so crowds spread over parallel streets.
+
+use super::{Actions, BuildPath, Target};
+use crate::{config::CongestionConfig, person::Person, simulation::SimClock};
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+
+/// People per square unit in every cell, smoothed over time.
+pub struct DensityLayer {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), f32>,
+}
+
+impl FromWorld for DensityLayer {
+    fn from_world(world: &mut World) -> Self {
+        let cell_size = world
+            .get_resource::<CongestionConfig>()
+            .map_or(CongestionConfig::default().cell_size, |config| {
+                config.cell_size
+            });
+        Self::new(cell_size)
+    }
+}
+
+impl DensityLayer {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn density(&self, pos: Vec2) -> f32 {
+        self.cells.get(&self.cell(pos)).copied().unwrap_or(0.0)
+    }
+
+    /// Moves the density of every cell by `blend` towards the density of the people at
+    /// `positions`.
+    pub fn update(&mut self, positions: impl Iterator<Item = Vec2>, blend: f32) {
+        let area = self.cell_size * self.cell_size;
+        let mut counts: HashMap<(i32, i32), f32> = HashMap::default();
+        for pos in positions {
+            *counts.entry(self.cell(pos)).or_default() += 1.0;
+        }
+        for (cell, density) in self.cells.iter_mut() {
+            let target = counts.remove(cell).unwrap_or(0.0) / area;
+            *density += blend * (target - *density);
+        }
+        for (cell, count) in counts {
+            self.cells.insert(cell, blend * count / area);
+        }
+        self.cells.retain(|_, density| *density > 0.001);
+    }
+
+    /// Highest density along the segment, sampled every half cell.
+    pub fn max_density_along(&self, from: Vec2, to: Vec2) -> f32 {
+        let samples = (from.distance(to) / (self.cell_size / 2.0)).ceil().max(1.0) as usize;
+        (0..=samples)
+            .map(|sample| self.density(from.lerp(to, sample as f32 / samples as f32)))
+            .fold(0.0, f32::max)
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        let cell = (pos / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+}
+
+/// Extra cost of crowded path nodes, read from the density layer.
+pub struct CongestionCost<'a> {
+    pub layer: &'a DensityLayer,
+    pub cost: f32,
+    pub congested_density: f32,
+}
+
+impl CongestionCost<'_> {
+    pub fn cost(&self, node: Vec2) -> f32 {
+        self.cost * self.layer.density(node)
+    }
+
+    /// Whether the segment crosses a congested place.
+    pub fn blocks(&self, from: Vec2, to: Vec2) -> bool {
+        self.layer.max_density_along(from, to) > self.congested_density
+    }
+}
+
+pub fn update_density_layer(
+    config: Res<CongestionConfig>,
+    clock: Res<SimClock>,
+    mut layer: ResMut<DensityLayer>,
+    people: Query<&Transform, With<Person>>,
+) {
+    if !config.enabled {
+        return;
+    }
+    let blend = (clock.delta().as_secs_f32() / config.smoothing_time).min(1.0);
+    layer.update(
+        people.iter().map(|transform| transform.translation.xy()),
+        blend,
+    );
+}
+
+type PlannerQuery<'a> = (Entity, &'a Transform, &'a Actions);
+
+/// Every `reroute_interval` seconds, replans the paths that go through a congested place.
+pub fn reroute_congested(
+    mut commands: Commands,
+    config: Res<CongestionConfig>,
+    clock: Res<SimClock>,
+    layer: Res<DensityLayer>,
+    mut next_check: Local<f32>,
+    planners: Query<PlannerQuery, (With<Target>, Without<BuildPath>)>,
+) {
+    let now = clock.elapsed_secs();
+    if !config.enabled || now < *next_check {
+        return;
+    }
+    *next_check = now + config.reroute_interval;
+
+    for (entity, transform, actions) in planners.iter() {
+        let pos = transform.translation.xy();
+        let waypoints = actions.upcoming_waypoints();
+        let first = match waypoints.first() {
+            Some(&first) => first,
+            None => continue,
+        };
+        // The crowd a person is walking in does not count, only the one ahead.
+        let mut from = pos + (first - pos).clamp_length_max(layer.cell_size());
+        let congested = waypoints.iter().any(|&waypoint| {
+            let congested = layer.max_density_along(from, waypoint) > config.congested_density;
+            from = waypoint;
+            congested
+        });
+        if congested {
+            debug!("Rerouting {:?} around congestion", entity);
+            commands.entity(entity).insert(BuildPath);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Crossing roads at the crosswalks of the signals: paths only step on a road at a crosswalk, and
+//! people wait at the kerb for the walk signal before crossing.
+
+use super::{group::Group, Action, Actions, WaitFor};
+use crate::{metrics::SimMetrics, signal::RoadAreas, simulation::SimClock};
+use bevy::prelude::*;
+
+/// Steps walking `path`, with a wait for the walk signal at the kerb before every crosswalk it
+/// crosses a road on. Waypoints on the road are replaced by the kerbs on both sides.
+pub fn walk_path(path: &[Vec2], road_areas: &RoadAreas, kerb_margin: f32) -> Vec<Action> {
+    let mut actions = vec![];
+    let mut last_off_road = None;
+    let mut crosswalk = None;
+    for &point in path {
+        if road_areas.on_road(point) {
+            crosswalk = crosswalk.or_else(|| road_areas.crosswalk_at(point).copied());
+            continue;
+        }
+        if let Some(from) = last_off_road {
+            if let Some(crosswalk) =
+                crosswalk.or_else(|| road_areas.crosswalk_on(from, point).copied())
+            {
+                actions.push(Action::GoTo(crosswalk.kerb(from, kerb_margin)));
+                actions.push(Action::Wait(WaitFor::Crossing(crosswalk.center)));
+                actions.push(Action::GoTo(crosswalk.kerb(point, kerb_margin)));
+            }
+        }
+        crosswalk = None;
+        actions.push(Action::GoTo(point));
+        last_off_road = Some(point);
+    }
+    // A path ending on the road, at a crosswalk: still wait at the kerb before stepping on it.
+    match path.last() {
+        Some(&last) if road_areas.on_road(last) => {
+            if let (Some(from), Some(crosswalk)) = (last_off_road, crosswalk) {
+                actions.push(Action::GoTo(crosswalk.kerb(from, kerb_margin)));
+                actions.push(Action::Wait(WaitFor::Crossing(crosswalk.center)));
+            }
+            actions.push(Action::GoTo(last));
+        }
+        _ => {}
+    }
+    actions
+}
+
+/// Lets the people and groups waiting at a crosswalk go once it gets the walk signal, and records
+/// how long they waited.
+pub fn wait_for_walk_signal(
+    clock: Res<SimClock>,
+    road_areas: Res<RoadAreas>,
+    mut metrics: ResMut<SimMetrics>,
+    mut waiting: Query<(&mut Actions, Option<&Group>)>,
+) {
+    let dt = clock.delta().as_secs_f32();
+    for (mut actions, group) in waiting.iter_mut() {
+        let center = match actions.current() {
+            Some(Action::Wait(WaitFor::Crossing(center))) => *center,
+            _ => continue,
+        };
+        let people = group.map_or(1, |group| group.members.len()) as u32;
+        match road_areas.crosswalk(center) {
+            Some(crosswalk) if !crosswalk.walk => {
+                metrics.crossing_wait_time += dt * people as f32;
+            }
+            // Also go when the crosswalk is gone with its signal.
+            _ => {
+                metrics.crossings += people;
+                actions.next();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::Crosswalk;
+
+    const KERB_MARGIN: f32 = 0.5;
+
+    /// A street along the x axis, 8 wide, with crosswalks at `crosswalk_xs`.
+    fn street(crosswalk_xs: &[f32]) -> RoadAreas {
+        let mut road_areas = RoadAreas::default();
+        road_areas.add_road(Vec2::new(-50.0, 0.0), Vec2::new(50.0, 0.0), 4.0);
+        for &x in crosswalk_xs {
+            road_areas.add_crosswalk(Crosswalk {
+                center: Vec2::new(x, 0.0),
+                across: Vec2::Y,
+                half_length: 4.0,
+                half_width: 1.5,
+                walk: false,
+            });
+        }
+        road_areas
+    }
+
+    fn goto(x: f32, y: f32) -> Action {
+        Action::GoTo(Vec2::new(x, y))
+    }
+
+    fn wait(x: f32) -> Action {
+        Action::Wait(WaitFor::Crossing(Vec2::new(x, 0.0)))
+    }
+
+    #[test]
+    fn waypoints_on_the_road_are_replaced_by_kerbs() {
+        let path = [
+            Vec2::new(0.0, -10.0),
+            Vec2::new(0.5, 1.0),
+            Vec2::new(0.0, 10.0),
+        ];
+        let actions = walk_path(&path, &street(&[0.0]), KERB_MARGIN);
+        assert_eq!(
+            actions,
+            vec![
+                goto(0.0, -10.0),
+                goto(0.0, -4.5),
+                wait(0.0),
+                goto(0.0, 4.5),
+                goto(0.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_before_a_crosswalk_crossed_between_waypoints() {
+        let path = [Vec2::new(0.0, -10.0), Vec2::new(0.0, 10.0)];
+        let actions = walk_path(&path, &street(&[0.0]), KERB_MARGIN);
+        assert_eq!(
+            actions,
+            vec![
+                goto(0.0, -10.0),
+                goto(0.0, -4.5),
+                wait(0.0),
+                goto(0.0, 4.5),
+                goto(0.0, 10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_before_every_crosswalk() {
+        let path = [
+            Vec2::new(-20.0, -10.0),
+            Vec2::new(-20.0, 10.0),
+            Vec2::new(20.0, 10.0),
+            Vec2::new(20.0, -10.0),
+        ];
+        let actions = walk_path(&path, &street(&[-20.0, 20.0]), KERB_MARGIN);
+        assert_eq!(
+            actions,
+            vec![
+                goto(-20.0, -10.0),
+                goto(-20.0, -4.5),
+                wait(-20.0),
+                goto(-20.0, 4.5),
+                goto(-20.0, 10.0),
+                goto(20.0, 10.0),
+                goto(20.0, 4.5),
+                wait(20.0),
+                goto(20.0, -4.5),
+                goto(20.0, -10.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn path_off_the_road_is_unchanged() {
+        let path = [Vec2::new(-20.0, 10.0), Vec2::new(20.0, 10.0)];
+        let actions = walk_path(&path, &street(&[0.0]), KERB_MARGIN);
+        assert_eq!(actions, vec![goto(-20.0, 10.0), goto(20.0, 10.0)]);
+    }
+
+    #[test]
+    fn path_ending_on_the_road_waits_at_the_kerb() {
+        let path = [Vec2::new(0.0, -10.0), Vec2::new(0.0, 1.0)];
+        let actions = walk_path(&path, &street(&[0.0]), KERB_MARGIN);
+        assert_eq!(
+            actions,
+            vec![goto(0.0, -10.0), goto(0.0, -4.5), wait(0.0), goto(0.0, 1.0)]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Groups of people walking together: the group entity holds the `Target` and `Actions` shared by
+//! its members, who walk to its current waypoint in formation and wait there for the stragglers
+//! before going on. Members only leave the group when obstacles force them to.
+
+use super::{Action, Actions, BuildPath, Target, TargetDoor, WaitFor};
+use crate::{
+    config::{Formation, GroupConfig},
+    person::{GroupMember, Person, PersonState, Walker},
+};
+use bevy::{math::Vec3Swizzles, prelude::*};
+
+/// People walking together, in formation order. The transform of the group follows the center of
+/// its members, which its paths are planned from.
+#[derive(Component, Debug)]
+pub struct Group {
+    pub members: Vec<Entity>,
+}
+
+/// Spawns a group made of `members`, which must not be part of another group.
+pub fn add_group(commands: &mut Commands, members: Vec<Entity>, pos: Vec2) -> Entity {
+    let group_entity = commands
+        .spawn()
+        .insert(Transform::from_translation(pos.extend(0.0)))
+        .id();
+    for &member in &members {
+        commands.entity(member).insert(GroupMember(group_entity));
+    }
+    commands.entity(group_entity).insert(Group { members });
+    group_entity
+}
+
+type GroupQuery<'a> = (
+    Entity,
+    &'a mut Group,
+    &'a mut Transform,
+    &'a Target,
+    Option<&'a TargetDoor>,
+    Option<&'a mut Actions>,
+);
+
+type MemberQuery<'a> = (
+    &'a Transform,
+    Option<&'a mut Actions>,
+    Option<&'a BuildPath>,
+);
+
+/// Moves the groups to the center of their members, lets the members that had to replan go on
+/// alone, advances the shared plans once everyone gathered at the waypoint and hands the current
+/// step to the members.
+pub fn update_groups(
+    mut commands: Commands,
+    config: Res<GroupConfig>,
+    mut groups: Query<GroupQuery, Without<GroupMember>>,
+    mut members: Query<MemberQuery, (With<GroupMember>, Without<Group>)>,
+) {
+    for (group_entity, mut group, mut transform, target, target_door, actions) in groups.iter_mut()
+    {
+        // Members that arrived or left on their own are forgotten.
+        group.members.retain(|&member| members.get(member).is_ok());
+
+        let walking_together = actions
+            .as_ref()
+            .is_some_and(|actions| is_walking_together(actions));
+        let positions: Vec<Vec2> = group
+            .members
+            .iter()
+            .map(|&member| members.get(member).unwrap().0.translation.xy())
+            .collect();
+        let sum: Vec2 = positions.iter().sum();
+        let leaving: Vec<Entity> = group
+            .members
+            .iter()
+            .zip(&positions)
+            .filter(|&(&member, &pos)| {
+                let replanning = members.get(member).unwrap().2.is_some();
+                let others = positions.len() as f32 - 1.0;
+                let separated = walking_together
+                    && others > 0.0
+                    && pos.distance((sum - pos) / others) > config.split_distance;
+                replanning || separated
+            })
+            .map(|(&member, _)| member)
+            .collect();
+        for &member in &leaving {
+            debug!("{:?} leaves group {:?}", member, group_entity);
+            let mut member_commands = commands.entity(member);
+            member_commands
+                .remove::<GroupMember>()
+                .insert(Target(**target))
+                .insert(BuildPath);
+            if let Some(target_door) = target_door {
+                member_commands.insert(TargetDoor(**target_door));
+            }
+        }
+        group.members.retain(|member| !leaving.contains(member));
+
+        if group.members.is_empty() {
+            commands.entity(group_entity).despawn();
+            continue;
+        }
+        let center = group
+            .members
+            .iter()
+            .map(|&member| members.get(member).unwrap().0.translation.xy())
+            .fold(Vec2::ZERO, |sum, pos| sum + pos)
+            / group.members.len() as f32;
+        transform.translation.x = center.x;
+        transform.translation.y = center.y;
+
+        let mut actions = match actions {
+            Some(actions) => actions,
+            // The shared plan is being built.
+            None => continue,
+        };
+        // Waits at crosswalks are ended by the signals.
+        if walking_together {
+            if let Some(Action::GoTo(waypoint)) = actions.current() {
+                let waypoint = *waypoint;
+                let gathered = group.members.iter().all(|&member| {
+                    members
+                        .get(member)
+                        .unwrap()
+                        .0
+                        .translation
+                        .xy()
+                        .distance(waypoint)
+                        <= config.gather_radius
+                });
+                if gathered {
+                    actions.next();
+                }
+            }
+        }
+
+        let plan_changed = actions.is_changed();
+        let plan = member_plan(&actions);
+        for &member in &group.members {
+            match members.get_mut(member).unwrap().1 {
+                Some(mut member_actions) if plan_changed => *member_actions = plan.clone(),
+                Some(_) => {}
+                None => {
+                    commands.entity(member).insert(plan.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Whether the group still walks to another waypoint together after the current step, which is a
+/// waypoint or a crosswalk.
+fn is_walking_together(actions: &Actions) -> bool {
+    matches!(
+        actions.current(),
+        Some(Action::GoTo(_) | Action::Wait(WaitFor::Crossing(_)))
+    ) && actions
+        .remaining()
+        .skip(1)
+        .any(|action| matches!(action, Action::GoTo(_)))
+}
+
+/// Plan of the members of a group: its current waypoint, where they wait for each other, waiting
+/// together at a crosswalk, or the rest of the plan once on the last leg, so they queue at the
+/// door and go in on their own.
+fn member_plan(actions: &Actions) -> Actions {
+    if !is_walking_together(actions) {
+        return Actions::from(actions.remaining().cloned().collect::<Vec<_>>());
+    }
+    match actions.current() {
+        Some(Action::GoTo(waypoint)) => {
+            Actions::from(vec![Action::GoTo(*waypoint), Action::Wait(WaitFor::Group)])
+        }
+        _ => Actions::from(vec![Action::Wait(WaitFor::Group)]),
+    }
+}
+
+type FormationQuery<'a> = (
+    &'a Transform,
+    &'a mut Person,
+    &'a Actions,
+    Option<&'a Walker>,
+);
+
+/// Adjusts the velocity of walking members to keep their place in the formation, at the speed of
+/// the slowest of them.
+pub fn keep_formation(
+    config: Res<GroupConfig>,
+    groups: Query<(&Group, &Transform, &Actions)>,
+    mut members: Query<FormationQuery, (With<GroupMember>, Without<Group>)>,
+) {
+    for (group, group_transform, group_actions) in groups.iter() {
+        let center = group_transform.translation.xy();
+        let dir = match group_actions.current() {
+            Some(Action::GoTo(waypoint)) => (*waypoint - center).normalize_or_zero(),
+            _ => continue,
+        };
+        let slowest = group
+            .members
+            .iter()
+            .filter_map(|&member| members.get(member).ok())
+            .map(|(_, _, _, walker)| walker.copied().unwrap_or_default().preferred_speed)
+            .fold(f32::INFINITY, f32::min);
+        let reference = match config.formation {
+            Formation::Abreast => center,
+            Formation::LeaderFollower => {
+                match group.members.first().map(|&leader| members.get(leader)) {
+                    Some(Ok((leader_transform, ..))) => leader_transform.translation.xy(),
+                    _ => continue,
+                }
+            }
+        };
+
+        let size = group.members.len() as f32;
+        for (index, &member) in group.members.iter().enumerate() {
+            let (transform, mut person, actions, walker) = match members.get_mut(member) {
+                Ok(member) => member,
+                Err(_) => continue,
+            };
+            let velocity = match (actions.current(), &person.state) {
+                (Some(Action::GoTo(_)), PersonState::Walking(velocity)) => *velocity,
+                _ => continue,
+            };
+            let (along, across) = match config.formation {
+                Formation::Abreast => (0.0, (index as f32 - (size - 1.0) / 2.0) * config.spacing),
+                Formation::LeaderFollower => (-(index as f32) * config.spacing, 0.0),
+            };
+            let slot = reference + along * dir + across * dir.perp();
+            let speed_ratio = slowest / walker.copied().unwrap_or_default().preferred_speed;
+            let correction = config.formation_gain * (slot - transform.translation.xy());
+            person.state =
+                PersonState::Walking((speed_ratio * velocity + correction).clamp_length_max(1.0));
+        }
+    }
+}
@@ -1,20 +1,141 @@
+pub mod congestion;
+pub mod crossing;
+pub mod group;
 pub mod path_debug;
+pub mod queue;
 pub mod search;
+pub mod steering;
+pub mod stuck;
+pub mod transit;
 
-use crate::person::*;
-use bevy::{math::Vec3Swizzles, prelude::*};
+use crate::{
+    config::{
+        CongestionConfig, DoorConfig, GroupConfig, PathfindingConfig, SignalConfig, SpawnConfig,
+        StuckConfig, TransitConfig,
+    },
+    metrics::SimMetrics,
+    person::*,
+    road::RoadGraph,
+    signal::{RoadAreas, SignalLabel},
+    simulation::SimClock,
+    spatial_hash::SpatialHash,
+    transit::TransitLine,
+};
+use bevy::{math::Vec3Swizzles, prelude::*, utils::Instant};
 use bevy_rapier2d::prelude::*;
+use congestion::{reroute_congested, update_density_layer, CongestionCost, DensityLayer};
+use crossing::wait_for_walk_signal;
+use group::{keep_formation, update_groups, Group};
+use queue::{add_door_queues, door_queues};
+use search::SoftObstacles;
+use serde::{Deserialize, Serialize};
+use stuck::{track_progress, AvoidCrowd, GaveUp, Stuck};
+use transit::NoRide;
 
-#[derive(Component, Debug)]
+/// Path planning and plan following for people with a `Target`.
+pub struct CrowdAiPlugin;
+
+#[derive(SystemLabel)]
+pub enum CrowdAiLabel {
+    Congestion,
+    PathUpdate,
+    Crossings,
+    Groups,
+    DoorQueues,
+    PersonActions,
+    Formation,
+    TrackProgress,
+    BuildPath,
+}
+
+impl Plugin for CrowdAiPlugin {
+    fn build(&self, app: &mut App) {
+        crate::add_event_once::<InvalidatePaths>(app);
+        crate::add_event_once::<Arrived>(app);
+        crate::add_event_once::<TripFailed>(app);
+        crate::add_event_once::<Stuck>(app);
+        crate::add_event_once::<GaveUp>(app);
+        app.init_resource::<PathfindingConfig>()
+            .init_resource::<StuckConfig>()
+            .init_resource::<CongestionConfig>()
+            .init_resource::<DensityLayer>()
+            .init_resource::<DoorConfig>()
+            .init_resource::<GroupConfig>()
+            .init_resource::<SpawnConfig>()
+            .init_resource::<SignalConfig>()
+            .init_resource::<TransitConfig>()
+            .init_resource::<RoadAreas>()
+            .init_resource::<RoadGraph>()
+            .add_system(invalidate_paths)
+            .add_system(add_door_queues)
+            .add_system(update_density_layer.label(CrowdAiLabel::Congestion))
+            .add_system(reroute_congested.after(CrowdAiLabel::Congestion))
+            .add_system(path_update.label(CrowdAiLabel::PathUpdate))
+            .add_system(
+                wait_for_walk_signal
+                    .label(CrowdAiLabel::Crossings)
+                    .after(CrowdAiLabel::PathUpdate)
+                    .after(SignalLabel::RoadAreas),
+            )
+            .add_system(
+                update_groups
+                    .label(CrowdAiLabel::Groups)
+                    .after(CrowdAiLabel::Crossings),
+            )
+            .add_system(
+                door_queues
+                    .label(CrowdAiLabel::DoorQueues)
+                    .after(CrowdAiLabel::Groups),
+            )
+            .add_system(
+                person_actions
+                    .label(CrowdAiLabel::PersonActions)
+                    .after(CrowdAiLabel::DoorQueues),
+            )
+            .add_system(
+                keep_formation
+                    .label(CrowdAiLabel::Formation)
+                    .after(CrowdAiLabel::PersonActions)
+                    .before(PersonLabel::Movement),
+            )
+            .add_system(
+                track_progress
+                    .label(CrowdAiLabel::TrackProgress)
+                    .after(PersonLabel::Movement),
+            )
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                build_path.label(CrowdAiLabel::BuildPath),
+            );
+    }
+}
+
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub struct Actions {
     steps: Vec<Action>,
     current_step: usize,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     GoTo(Vec2),
+    Wait(WaitFor),
     Despawn,
+    /// Gives up the trip, as no path leads to the target.
+    Fail,
+}
+
+/// What a waiting person waits for. The step is finished by the system managing it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WaitFor {
+    /// Its turn in the queue of the door at this position.
+    Door(Vec2),
+    /// The rest of its group, to go on to the next waypoint together.
+    Group,
+    /// The walk signal of the crosswalk centered on this position.
+    Crossing(Vec2),
+    /// A bus at the stop at `board` that goes on to the stop at `alight`, to ride it there.
+    Bus { board: Vec2, alight: Vec2 },
 }
 
 impl Actions {
@@ -33,6 +154,61 @@ impl Actions {
     fn peek(&self) -> Option<&Action> {
         self.steps.get(self.current_step + 1)
     }
+
+    /// Targets of the current step and of the `GoTo` steps right after it.
+    fn upcoming_waypoints(&self) -> Vec<Vec2> {
+        self.remaining()
+            .map_while(|action| match action {
+                Action::GoTo(target) => Some(*target),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Length of the path left to walk from `pos` through the upcoming waypoints.
+    fn remaining_distance(&self, pos: Vec2) -> f32 {
+        let mut distance = 0.0;
+        let mut from = pos;
+        for waypoint in self.upcoming_waypoints() {
+            distance += from.distance(waypoint);
+            from = waypoint;
+        }
+        distance
+    }
+
+    /// What the person waits for once done with the upcoming waypoints, if anything.
+    fn next_wait(&self) -> Option<WaitFor> {
+        match self
+            .remaining()
+            .find(|action| !matches!(action, Action::GoTo(_)))
+        {
+            Some(Action::Wait(wait_for)) => Some(*wait_for),
+            _ => None,
+        }
+    }
+
+    /// Drops the upcoming waypoints after the first `count` of them.
+    fn truncate_waypoints(&mut self, count: usize) {
+        let waypoints = self.upcoming_waypoints().len();
+        if count < waypoints {
+            let start = self.current_step + count;
+            self.steps.drain(start..self.current_step + waypoints);
+        }
+    }
+
+    /// Skips the upcoming waypoints, to go straight to the step after them.
+    fn skip_waypoints(&mut self) {
+        while let Some(Action::GoTo(_)) = self.current() {
+            self.next();
+        }
+    }
+
+    fn previous_waypoint(&self) -> Option<Vec2> {
+        match self.steps.get(self.current_step.checked_sub(1)?) {
+            Some(Action::GoTo(target)) => Some(*target),
+            _ => None,
+        }
+    }
 }
 
 impl From<Vec<Action>> for Actions {
@@ -50,18 +226,67 @@ pub struct Target(pub Vec2);
 #[derive(Component, Debug)]
 pub struct BuildPath;
 
+/// Door a person goes through at the end of its trip, after queueing in front of it.
+#[derive(Component, Deref, Debug)]
+pub struct TargetDoor(pub Vec2);
+
+/// Where a waiting person stands.
+#[derive(Component, Deref, Debug)]
+pub struct WaitSpot(pub Vec2);
+
+/// Seconds the way to the current waypoint has been blocked for.
+#[derive(Component, Deref, Debug)]
+pub struct Blocked(f32);
+
+/// Sent when the map geometry changes, so that existing paths are rebuilt.
+pub struct InvalidatePaths;
+
+/// Sent when a person reaches the end of its plan, right before it is despawned.
+pub struct Arrived(pub Entity);
+
+/// Sent when a person gives up its trip as no path leads to its target, right before it is
+/// despawned.
+pub struct TripFailed(pub Entity);
+
 pub fn person_actions(
     mut commands: Commands,
-    mut people: Query<(Entity, &mut Person, &Transform, &Actions)>,
+    config: Res<PathfindingConfig>,
+    mut people: Query<(Entity, &mut Person, &Transform, &Actions, Option<&WaitSpot>)>,
+    mut arrivals: EventWriter<Arrived>,
+    mut failures: EventWriter<TripFailed>,
 ) {
-    for (person_entity, mut person, person_transform, actions) in people.iter_mut() {
+    for (person_entity, mut person, person_transform, actions, wait_spot) in people.iter_mut() {
         if let Some(action) = actions.current() {
             match action {
-                Action::GoTo(target) => {
-                    let dir = (*target - person_transform.translation.xy()).normalize_or_zero();
-                    person.state = PersonState::Walking(dir);
+                Action::GoTo(_) => {
+                    let velocity = steering::follow_path(
+                        person_transform.translation.xy(),
+                        actions.previous_waypoint(),
+                        &actions.upcoming_waypoints(),
+                        &config,
+                    );
+                    person.state = PersonState::Walking(velocity);
+                }
+                Action::Wait(_) => {
+                    let pos = person_transform.translation.xy();
+                    person.state = match wait_spot {
+                        Some(wait_spot) if pos.distance(**wait_spot) > config.arrival_radius => {
+                            PersonState::Walking(steering::follow_path(
+                                pos,
+                                None,
+                                &[**wait_spot],
+                                &config,
+                            ))
+                        }
+                        _ => PersonState::Standing,
+                    };
                 }
                 Action::Despawn => {
+                    arrivals.send(Arrived(person_entity));
+                    commands.entity(person_entity).despawn();
+                }
+                Action::Fail => {
+                    failures.send(TripFailed(person_entity));
                     commands.entity(person_entity).despawn();
                 }
             }
@@ -71,18 +296,44 @@ pub fn person_actions(
     }
 }
 
+type PathUpdateQuery<'a> = (
+    Entity,
+    &'a Transform,
+    &'a mut Actions,
+    Option<&'a Walker>,
+    Option<&'a mut Blocked>,
+);
+
 pub fn path_update(
     mut commands: Commands,
     rapier_ctx: Res<RapierContext>,
-    mut transform_and_actions: Query<(Entity, &Transform, &mut Actions)>,
+    config: Res<PathfindingConfig>,
+    clock: Res<SimClock>,
+    mut transform_and_actions: Query<PathUpdateQuery, Without<Group>>,
 ) {
-    for (entity, transform, mut actions) in transform_and_actions.iter_mut() {
-        rebuild_actions_if_stuck(&mut commands, &rapier_ctx, entity, transform, &mut actions);
-        check_step_finshed(&rapier_ctx, transform, &mut actions);
+    for (entity, transform, mut actions, walker, blocked) in transform_and_actions.iter_mut() {
+        let patience = walker.copied().unwrap_or_default().patience;
+        rebuild_actions_if_stuck(
+            &mut commands,
+            &rapier_ctx,
+            &config,
+            entity,
+            transform,
+            &mut actions,
+            blocked,
+            clock.delta().as_secs_f32(),
+            patience,
+        );
+        check_step_finshed(&rapier_ctx, &config, transform, &mut actions);
     }
 }
 
-fn check_step_finshed(rapier_ctx: &RapierContext, transform: &Transform, actions: &mut Actions) {
+fn check_step_finshed(
+    rapier_ctx: &RapierContext,
+    config: &PathfindingConfig,
+    transform: &Transform,
+    actions: &mut Actions,
+) {
     let finished_step = if let Some(Action::GoTo(target)) = actions.current() {
         let pos = transform.translation.xy();
 
@@ -95,13 +346,13 @@ fn check_step_finshed(rapier_ctx: &RapierContext, transform: &Transform, actions
                 pos,
                 0.0,
                 dir,
-                &Collider::cuboid(0.5, 0.5),
+                &Collider::cuboid(config.clearance, config.clearance),
                 distance,
                 QueryFilter::only_fixed(),
             );
             result.is_none()
         } else {
-            pos.distance(*target) < 0.5
+            pos.distance(*target) < config.arrival_radius
         }
     } else {
         false
@@ -111,14 +362,21 @@ fn check_step_finshed(rapier_ctx: &RapierContext, transform: &Transform, actions
     }
 }
 
+/// Replans once the way to the current waypoint has been blocked by a fixed collider for longer
+/// than the patience of the person.
+#[allow(clippy::too_many_arguments)]
 fn rebuild_actions_if_stuck(
     commands: &mut Commands,
     rapier_ctx: &RapierContext,
+    config: &PathfindingConfig,
     entity: Entity,
     transform: &Transform,
     actions: &mut Actions,
+    blocked: Option<Mut<Blocked>>,
+    dt: f32,
+    patience: f32,
 ) {
-    let rebuild = if let Some(Action::GoTo(target)) = actions.current() {
+    let is_blocked = if let Some(Action::GoTo(target)) = actions.current() {
         let pos = transform.translation.xy();
         let displacement = *target - pos;
         let distance = displacement.length();
@@ -127,7 +385,7 @@ fn rebuild_actions_if_stuck(
             pos,
             0.0,
             dir,
-            &Collider::cuboid(0.5, 0.5),
+            &Collider::cuboid(config.clearance, config.clearance),
             distance,
             QueryFilter::only_fixed(),
         );
@@ -135,39 +393,191 @@ fn rebuild_actions_if_stuck(
     } else {
         false
     };
-    if rebuild {
-        info!("Rebuilding path for {:?}", entity);
+    let blocked_time = blocked.as_ref().map_or(0.0, |blocked| blocked.0) + dt;
+    match blocked {
+        Some(_) if !is_blocked => {
+            commands.entity(entity).remove::<Blocked>();
+        }
+        None if !is_blocked => {}
+        _ if blocked_time >= patience => {
+            info!("Rebuilding path for {:?}", entity);
+            commands
+                .entity(entity)
+                .insert(BuildPath)
+                .remove::<Blocked>();
+        }
+        Some(mut blocked) => blocked.0 = blocked_time,
+        None => {
+            commands.entity(entity).insert(Blocked(blocked_time));
+        }
+    }
+}
+
+pub fn invalidate_paths(
+    mut commands: Commands,
+    mut events: EventReader<InvalidatePaths>,
+    people: Query<Entity, (With<Target>, With<Actions>)>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+    for entity in people.iter() {
         commands.entity(entity).insert(BuildPath);
     }
 }
 
+type ToBuildQuery<'a> = (
+    Entity,
+    &'a Transform,
+    &'a Target,
+    Option<&'a TargetDoor>,
+    Option<&'a Walker>,
+    Option<&'a Group>,
+    Option<&'a AvoidCrowd>,
+    Option<&'a NoRide>,
+);
+
+#[allow(clippy::too_many_arguments)]
 pub fn build_path(
     mut commands: Commands,
     rapier_ctx: Res<RapierContext>,
-    to_build: Query<(Entity, &Transform, &Target), With<BuildPath>>,
+    config: Res<PathfindingConfig>,
+    stuck_config: Res<StuckConfig>,
+    congestion_config: Res<CongestionConfig>,
+    signal_config: Res<SignalConfig>,
+    transit_config: Res<TransitConfig>,
+    spatial_hash: Res<SpatialHash>,
+    density_layer: Res<DensityLayer>,
+    road_areas: Res<RoadAreas>,
+    graph: Res<RoadGraph>,
+    mut metrics: ResMut<SimMetrics>,
+    to_build: Query<ToBuildQuery, With<BuildPath>>,
+    lines: Query<&TransitLine>,
 ) {
-    for (entity, transform, target) in to_build.iter() {
-        let mut actions = vec![];
+    for (entity, transform, target, target_door, walker, group, avoid_crowd, no_ride) in
+        to_build.iter()
+    {
+        let start = Instant::now();
         let from = transform.translation.xy();
-        let raw_path = search::search_path(&rapier_ctx, from, **target).unwrap();
-        let simplified_path = path_simplification(&rapier_ctx, raw_path);
-        actions.extend(simplified_path.into_iter().map(Action::GoTo));
-        actions.push(Action::Despawn);
+        let soft_obstacles = if avoid_crowd.is_some() {
+            SoftObstacles {
+                positions: spatial_hash
+                    .within_radius(from, stuck_config.crowd_distance)
+                    .filter(|other| other.entity != entity)
+                    .map(|other| other.pos)
+                    .collect(),
+                radius: stuck_config.soft_obstacle_radius,
+                cost: stuck_config.soft_obstacle_cost,
+            }
+        } else {
+            SoftObstacles::default()
+        };
+        let congestion = congestion_config.enabled.then_some(CongestionCost {
+            layer: &density_layer,
+            cost: congestion_config.cost,
+            congested_density: congestion_config.congested_density,
+        });
+        let walk = |from: Vec2, to: Vec2| {
+            // Someone who is already on the road, or going there, walks straight off or onto it.
+            let road_areas =
+                (road_areas.walkable(from) && road_areas.walkable(to)).then_some(&*road_areas);
+            let path = match search::search_path(
+                &rapier_ctx,
+                &config,
+                &soft_obstacles,
+                congestion.as_ref(),
+                road_areas,
+                from,
+                to,
+            ) {
+                Some(raw_path) if config.simplify_paths => path_simplification(
+                    &rapier_ctx,
+                    &config,
+                    &soft_obstacles,
+                    congestion.as_ref(),
+                    road_areas,
+                    raw_path,
+                ),
+                Some(raw_path) => raw_path,
+                None => {
+                    warn!("No path found for {:?}", entity);
+                    return None;
+                }
+            };
+            Some(match road_areas {
+                Some(road_areas) => {
+                    crossing::walk_path(&path, road_areas, signal_config.kerb_margin)
+                }
+                None => path.into_iter().map(Action::GoTo).collect(),
+            })
+        };
+        // People going to a door on their own take the bus when it gets them there sooner, unless
+        // they already gave up waiting for one.
+        let rides = transit_config.enabled && no_ride.is_none();
+        let ride = if rides && target_door.is_some() && group.is_none() {
+            let walk_speed = walker.copied().unwrap_or_default().preferred_speed;
+            transit::plan_ride(
+                lines.iter(),
+                &graph,
+                &transit_config,
+                from,
+                **target,
+                walk_speed,
+            )
+        } else {
+            None
+        };
+        let planned = ride
+            .and_then(|ride| {
+                let mut actions = walk(from, ride.board)?;
+                actions.push(Action::Wait(WaitFor::Bus {
+                    board: ride.board,
+                    alight: ride.alight,
+                }));
+                actions.extend(walk(ride.alight, **target)?);
+                Some(actions)
+            })
+            .or_else(|| walk(from, **target));
+        let actions = match planned {
+            Some(mut actions) => {
+                if let Some(target_door) = target_door {
+                    actions.push(Action::Wait(WaitFor::Door(**target_door)));
+                }
+                actions.push(Action::Despawn);
+                actions
+            }
+            // Never counted as an arrival.
+            None => vec![Action::Fail],
+        };
+        metrics.paths_built += 1;
+        metrics.pathfinding_time += start.elapsed();
 
         commands
             .entity(entity)
             .insert(Actions::from(actions))
-            .remove::<BuildPath>();
+            .remove::<BuildPath>()
+            .remove::<AvoidCrowd>();
     }
 }
 
-fn path_simplification(rapier_ctx: &RapierContext, path: Vec<Vec2>) -> Vec<Vec2> {
+fn path_simplification(
+    rapier_ctx: &RapierContext,
+    config: &PathfindingConfig,
+    soft_obstacles: &SoftObstacles,
+    congestion: Option<&CongestionCost>,
+    road_areas: Option<&RoadAreas>,
+    path: Vec<Vec2>,
+) -> Vec<Vec2> {
     let mut simplified_path = vec![path[0]];
     let mut i = 1;
     while i < path.len() - 1 {
-        if !can_see(rapier_ctx, *simplified_path.last().unwrap(), path[i + 1]) {
+        let last = *simplified_path.last().unwrap();
+        if !can_see(rapier_ctx, config, last, path[i + 1])
+            || soft_obstacles.blocks(last, path[i + 1])
+            || congestion.is_some_and(|congestion| congestion.blocks(last, path[i + 1]))
+            || road_areas.is_some_and(|road_areas| road_areas.blocks(last, path[i + 1]))
+        {
             simplified_path.push(path[i])
-        } else {
         }
         i += 1;
     }
@@ -176,14 +586,14 @@ fn path_simplification(rapier_ctx: &RapierContext, path: Vec<Vec2>) -> Vec<Vec2>
     simplified_path
 }
 
-fn can_see(rapier_ctx: &RapierContext, from: Vec2, to: Vec2) -> bool {
+fn can_see(rapier_ctx: &RapierContext, config: &PathfindingConfig, from: Vec2, to: Vec2) -> bool {
     let dir = (to - from).normalize();
     rapier_ctx
         .cast_shape(
             from,
             0.0,
             dir,
-            &Collider::cuboid(0.5, 0.5),
+            &Collider::cuboid(config.clearance, config.clearance),
             from.distance(to),
             QueryFilter::only_fixed(),
         )
@@ -1,4 +1,5 @@
 use super::{Action, Actions};
+use crate::debug::DebugConfig;
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
 use itertools::Itertools;
@@ -9,11 +10,14 @@ pub struct PathDebugRef(Entity);
 #[derive(Component, Deref)]
 pub struct PathDebug(Entity);
 
-#[allow(dead_code)]
 pub fn path_debug(
     mut commands: Commands,
+    config: Res<DebugConfig>,
     changed_paths: Query<(Entity, &Actions, Option<&PathDebugRef>), Changed<Actions>>,
 ) {
+    if !config.path_debug {
+        return;
+    }
     for (entity, path, path_debug) in changed_paths.iter() {
         if let Some(path_debug) = path_debug {
             commands.entity(**path_debug).despawn();
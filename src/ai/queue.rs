@@ -0,0 +1,140 @@
+//! Door queues: people entering a building line up in front of its door and go in one after the
+//! other, no faster than the door capacity.
+
+use super::{can_see, Action, Actions, WaitFor, WaitSpot};
+use crate::{
+    building::Door,
+    config::{DoorConfig, PathfindingConfig, SpawnConfig},
+    person::Person,
+    simulation::SimClock,
+};
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashSet};
+use bevy_rapier2d::prelude::*;
+use std::collections::VecDeque;
+
+/// Distance under which a waiting position designates a door.
+const DOOR_MATCH_DISTANCE: f32 = 0.5;
+
+/// People waiting to go through a door, first in line at the front.
+#[derive(Component, Default)]
+pub struct DoorQueue {
+    people: VecDeque<Entity>,
+    /// The same people, to find them quickly.
+    members: HashSet<Entity>,
+    /// Simulation time at which the next person may go in.
+    next_entry: f32,
+}
+
+impl DoorQueue {
+    pub fn len(&self) -> usize {
+        self.people.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.people.is_empty()
+    }
+}
+
+pub fn add_door_queues(mut commands: Commands, doors: Query<Entity, Added<Door>>) {
+    for door_entity in doors.iter() {
+        commands.entity(door_entity).insert(DoorQueue::default());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn door_queues(
+    mut commands: Commands,
+    rapier_ctx: Res<RapierContext>,
+    config: Res<DoorConfig>,
+    pathfinding_config: Res<PathfindingConfig>,
+    spawn_config: Res<SpawnConfig>,
+    clock: Res<SimClock>,
+    mut doors: Query<(&GlobalTransform, &Door, &mut DoorQueue)>,
+    mut people: Query<(Entity, &Transform, &mut Actions, Option<&WaitSpot>), With<Person>>,
+) {
+    // People close enough to the door they are heading to take a place in its queue.
+    for (entity, transform, mut actions, _) in people.iter_mut() {
+        let door_pos = match actions.next_wait() {
+            Some(WaitFor::Door(door_pos)) => door_pos,
+            _ => continue,
+        };
+        if actions.remaining_distance(transform.translation.xy()) > config.join_distance {
+            continue;
+        }
+        let queue = doors.iter_mut().find(|(door_transform, _, _)| {
+            door_transform.translation().xy().distance(door_pos) < DOOR_MATCH_DISTANCE
+        });
+        let (door_transform, door, mut queue) = match queue {
+            Some(queue) => queue,
+            None => continue,
+        };
+        if !queue.members.insert(entity) {
+            continue;
+        }
+        let slot_pos = door_transform.translation().xy()
+            + (spawn_config.door_distance + queue.len() as f32 * config.slot_spacing)
+                * door.get_open_dir();
+        queue.people.push_back(entity);
+
+        // The rest of the path is cut short, but not around corners.
+        let pos = transform.translation.xy();
+        let sees_slot = |from: Vec2| can_see(&rapier_ctx, &pathfinding_config, from, slot_pos);
+        if sees_slot(pos) {
+            actions.skip_waypoints();
+        } else {
+            let waypoints = actions.upcoming_waypoints();
+            let keep = waypoints
+                .iter()
+                .position(|&waypoint| sees_slot(waypoint))
+                .map_or(waypoints.len(), |index| index + 1);
+            actions.truncate_waypoints(keep);
+        }
+    }
+
+    let now = clock.elapsed_secs();
+    for (door_transform, door, mut queue) in doors.iter_mut() {
+        let door_pos = door_transform.translation().xy();
+        let DoorQueue {
+            people: queued,
+            members,
+            ..
+        } = &mut *queue;
+        queued.retain(|&entity| {
+            // Including the people still on their last waypoints to the queue.
+            let waiting = people.get(entity).is_ok_and(|(_, _, actions, _)| {
+                matches!(
+                    actions.next_wait(),
+                    Some(WaitFor::Door(pos)) if pos.distance(door_pos) < DOOR_MATCH_DISTANCE
+                )
+            });
+            if !waiting {
+                members.remove(&entity);
+            }
+            waiting
+        });
+
+        let front_pos = door_pos + spawn_config.door_distance * door.get_open_dir();
+        for (index, &entity) in queue.people.iter().enumerate() {
+            let slot_pos = front_pos + index as f32 * config.slot_spacing * door.get_open_dir();
+            let (_, _, _, wait_spot) = people.get(entity).unwrap();
+            if wait_spot.is_none_or(|wait_spot| **wait_spot != slot_pos) {
+                commands.entity(entity).insert(WaitSpot(slot_pos));
+            }
+        }
+
+        if now < queue.next_entry {
+            continue;
+        }
+        if let Some(&first) = queue.people.front() {
+            let (_, transform, mut actions, _) = people.get_mut(first).unwrap();
+            let at_door = matches!(actions.current(), Some(Action::Wait(WaitFor::Door(_))));
+            if at_door && transform.translation.xy().distance(front_pos) <= config.slot_radius {
+                actions.next();
+                queue.people.pop_front();
+                queue.members.remove(&first);
+                queue.next_entry = now + 1.0 / config.capacity;
+                commands.entity(first).remove::<WaitSpot>();
+            }
+        }
+    }
+}
@@ -1,88 +1,195 @@
+use super::congestion::CongestionCost;
+use crate::{config::PathfindingConfig, person::closest_point_on_segment, signal::RoadAreas};
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use ordered_float::OrderedFloat;
 use priority_queue::PriorityQueue;
 use std::cmp::Reverse;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-pub fn search_path(rapier_ctx: &RapierContext, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
-    let mut closed = HashMap::new();
-    let mut open = PriorityQueue::new();
+/// Cost of the cheapest known way to every grid cell, and the cell it is reached from.
+type Costs = HashMap<(i32, i32), (f32, (i32, i32))>;
 
-    open.push(
-        (Vec2Wrapper(from), Vec2Wrapper(from)),
-        Reverse(OrderedFloat(from.distance(to))),
-    );
-    while let Some(((node, parent), _)) = open.pop() {
-        if closed.contains_key(&node) {
-            continue;
-        }
-        closed.insert(node, parent);
+/// People a path should go around when it can, making the grid nodes within `radius` of each
+/// of them cost `cost` more.
+#[derive(Default)]
+pub struct SoftObstacles {
+    pub positions: Vec<Vec2>,
+    pub radius: f32,
+    pub cost: f32,
+}
+
+impl SoftObstacles {
+    fn cost(&self, node: Vec2) -> f32 {
+        self.cost
+            * self
+                .positions
+                .iter()
+                .filter(|pos| pos.distance(node) < self.radius)
+                .count() as f32
+    }
+
+    /// Whether the segment passes close to one of the obstacles.
+    pub fn blocks(&self, from: Vec2, to: Vec2) -> bool {
+        self.positions
+            .iter()
+            .any(|&pos| closest_point_on_segment(pos, from, to).distance(pos) < self.radius)
+    }
+}
+
+pub fn search_path(
+    rapier_ctx: &RapierContext,
+    config: &PathfindingConfig,
+    soft_obstacles: &SoftObstacles,
+    congestion: Option<&CongestionCost>,
+    road_areas: Option<&RoadAreas>,
+    from: Vec2,
+    to: Vec2,
+) -> Option<Vec<Vec2>> {
+    let free = |node: Vec2| {
+        // Roads are only crossed at the crosswalks.
+        road_areas.is_none_or(|road_areas| road_areas.walkable(node))
+            && rapier_ctx
+                .intersection_with_shape(
+                    node,
+                    0.0,
+                    &Collider::cuboid(config.clearance, config.clearance),
+                    QueryFilter::only_fixed(),
+                )
+                .is_none()
+    };
+    let extra_cost = |node: Vec2| {
+        soft_obstacles.cost(node) + congestion.map_or(0.0, |congestion| congestion.cost(node))
+    };
+    search_grid(config, from, to, free, extra_cost)
+}
+
+/// A* over the grid of `grid_step` anchored at `from`, through the nodes that are `free`. Every
+/// step costs its length plus the `extra_cost` of the node it leads to, so a detour is taken
+/// whenever the costs it avoids add up to more than its length.
+fn search_grid(
+    config: &PathfindingConfig,
+    from: Vec2,
+    to: Vec2,
+    free: impl Fn(Vec2) -> bool,
+    extra_cost: impl Fn(Vec2) -> f32,
+) -> Option<Vec<Vec2>> {
+    let step = config.grid_step;
+    let pos = |(x, y): (i32, i32)| from + step * Vec2::new(x as f32, y as f32);
+    // The grid is anchored at `from`, so its closest node to `to` can be up to half a diagonal
+    // away.
+    let goal_radius = config.goal_radius.max(step * 0.75);
 
-        if node.distance(to) <= 0.8 {
-            return Some(build_path(node, from, closed));
+    // Cost of the cheapest way found to every node, and the node it comes from.
+    let mut costs: Costs = HashMap::new();
+    let mut closed = HashSet::new();
+    let mut open = PriorityQueue::new();
+    costs.insert((0, 0), (0.0, (0, 0)));
+    open.push((0, 0), Reverse(OrderedFloat(from.distance(to))));
+    while let Some((node, _)) = open.pop() {
+        closed.insert(node);
+        if closed.len() > config.max_expansions {
+            return None;
+        }
+        if pos(node).distance(to) <= goal_radius {
+            return Some(build_path(node, &costs, pos));
         }
 
-        for neighboor in neighboors(*node, rapier_ctx) {
-            open.push(
-                (Vec2Wrapper(neighboor), node),
-                Reverse(OrderedFloat(neighboor.distance(to))),
-            );
+        let cost = costs[&node].0;
+        let (x, y) = node;
+        for neighboor in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if closed.contains(&neighboor) || !free(pos(neighboor)) {
+                continue;
+            }
+            let neighboor_cost = cost + step + extra_cost(pos(neighboor));
+            if costs
+                .get(&neighboor)
+                .is_none_or(|&(known, _)| neighboor_cost < known)
+            {
+                costs.insert(neighboor, (neighboor_cost, node));
+                let priority = neighboor_cost + pos(neighboor).distance(to);
+                open.push(neighboor, Reverse(OrderedFloat(priority)));
+            }
         }
     }
 
     None
 }
 
-fn build_path(
-    node: Vec2Wrapper,
-    from: Vec2,
-    closed: HashMap<Vec2Wrapper, Vec2Wrapper>,
-) -> Vec<Vec2> {
-    let mut path = vec![*node];
-    while path[path.len() - 1].distance(from) > 0.1 {
-        path.push(*closed[&Vec2Wrapper(path[path.len() - 1])]);
+fn build_path(node: (i32, i32), costs: &Costs, pos: impl Fn((i32, i32)) -> Vec2) -> Vec<Vec2> {
+    let mut path = vec![pos(node)];
+    let mut node = node;
+    while node != (0, 0) {
+        node = costs[&node].1;
+        path.push(pos(node));
     }
 
     path.into_iter().rev().collect()
 }
 
-fn neighboors(node: Vec2, rapier_ctx: &RapierContext) -> Vec<Vec2> {
-    let neighboors = vec![
-        node + Vec2::X,
-        node - Vec2::X,
-        node + Vec2::Y,
-        node - Vec2::Y,
-    ];
-    neighboors
-        .into_iter()
-        .filter(|canditate| {
-            rapier_ctx
-                .intersection_with_shape(
-                    *canditate,
-                    0.0,
-                    &Collider::cuboid(0.5, 0.5),
-                    QueryFilter::only_fixed(),
-                )
-                .is_none()
-        })
-        .collect()
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::congestion::DensityLayer;
 
-#[derive(Deref, Clone, Copy, Debug)]
-struct Vec2Wrapper(Vec2);
+    /// Two corridors from `(0, 0)` to `(20, 0)`, around a block between `x = 2` and `x = 18`,
+    /// above and below it.
+    fn in_corridors(node: Vec2) -> bool {
+        let beside_block = node.x > 2.0 && node.x < 18.0 && node.y.abs() < 3.0;
+        node.x > -3.0 && node.x < 23.0 && node.y.abs() < 7.0 && !beside_block
+    }
 
-impl PartialEq for Vec2Wrapper {
-    fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+    /// Whether the path goes around the block through the corridor below it.
+    fn takes_lower_corridor(path: &[Vec2]) -> bool {
+        let along_block: Vec<Vec2> = path
+            .iter()
+            .copied()
+            .filter(|node| node.x > 4.0 && node.x < 16.0)
+            .collect();
+        assert!(!along_block.is_empty());
+        along_block.iter().all(|node| node.y < 0.0)
     }
-}
 
-impl Eq for Vec2Wrapper {}
+    fn search(extra_cost: impl Fn(Vec2) -> f32) -> Vec<Vec2> {
+        let config = PathfindingConfig::default();
+        let (from, to) = (Vec2::ZERO, Vec2::new(20.0, 0.0));
+        let path = search_grid(&config, from, to, in_corridors, extra_cost).unwrap();
+        assert_eq!(path[0], from);
+        assert!(path.last().unwrap().distance(to) <= config.goal_radius.max(0.75));
+        path
+    }
+
+    fn crowd_in_corridor(y: f32) -> Vec<Vec2> {
+        (2..18)
+            .flat_map(|x| (-1..=1).map(move |dy| Vec2::new(x as f32 + 0.5, y + dy as f32)))
+            .collect()
+    }
 
-impl std::hash::Hash for Vec2Wrapper {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        OrderedFloat(self.0.x).hash(state);
-        OrderedFloat(self.0.y).hash(state);
+    #[test]
+    fn path_takes_the_corridor_without_a_crowd() {
+        for (crowded_y, lower) in [(5.0, true), (-5.0, false)] {
+            let mut layer = DensityLayer::new(2.0);
+            layer.update(crowd_in_corridor(crowded_y).into_iter(), 1.0);
+            let congestion = CongestionCost {
+                layer: &layer,
+                cost: 20.0,
+                congested_density: 0.5,
+            };
+            let path = search(|node| congestion.cost(node));
+            assert_eq!(takes_lower_corridor(&path), lower);
+        }
+    }
+
+    #[test]
+    fn path_goes_around_soft_obstacles() {
+        for (crowded_y, lower) in [(5.0, true), (-5.0, false)] {
+            let soft_obstacles = SoftObstacles {
+                positions: crowd_in_corridor(crowded_y),
+                radius: 1.5,
+                cost: 5.0,
+            };
+            let path = search(|node| soft_obstacles.cost(node));
+            assert_eq!(takes_lower_corridor(&path), lower);
+        }
     }
 }
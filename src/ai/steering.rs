@@ -0,0 +1,122 @@
+//! Path following: people steer towards a point further along their path, pure pursuit style,
+//! and slow down before sharp corners and the end of the path.
+
+use crate::{config::PathfindingConfig, person::closest_point_on_segment};
+use bevy::prelude::*;
+
+/// Returns the walking velocity relative to the preferred speed of a person at `pos` following
+/// `waypoints`, coming from the `previous` waypoint if there is one.
+pub fn follow_path(
+    pos: Vec2,
+    previous: Option<Vec2>,
+    waypoints: &[Vec2],
+    config: &PathfindingConfig,
+) -> Vec2 {
+    let next = match waypoints.first() {
+        Some(next) => *next,
+        None => return Vec2::ZERO,
+    };
+    let lookahead = lookahead_point(pos, previous, waypoints, config.lookahead_distance);
+    // Without lookahead, a person on its path steers straight to the next waypoint.
+    let dir = (lookahead - pos)
+        .try_normalize()
+        .unwrap_or_else(|| (next - pos).normalize_or_zero());
+
+    let remaining = pos.distance(next)
+        + waypoints
+            .windows(2)
+            .map(|segment| segment[0].distance(segment[1]))
+            .sum::<f32>();
+    let mut speed = (remaining / config.slowing_radius).min(1.0);
+
+    if let Some(&after_next) = waypoints.get(1) {
+        let incoming = (next - previous.unwrap_or(pos)).normalize_or_zero();
+        let outgoing = (after_next - next).normalize_or_zero();
+        let corner_speed = ((1.0 + incoming.dot(outgoing)) / 2.0).max(config.min_corner_speed);
+        let approach = (pos.distance(next) / config.corner_slowing_radius).min(1.0);
+        speed = speed.min(corner_speed + (1.0 - corner_speed) * approach);
+    }
+
+    speed * dir
+}
+
+/// Point `lookahead_distance` along the path from the closest point of the current segment.
+fn lookahead_point(
+    pos: Vec2,
+    previous: Option<Vec2>,
+    waypoints: &[Vec2],
+    lookahead_distance: f32,
+) -> Vec2 {
+    let mut start = match previous {
+        Some(previous) => closest_point_on_segment(pos, previous, waypoints[0]),
+        None => pos,
+    };
+    let mut remaining = lookahead_distance;
+    for &waypoint in waypoints {
+        let segment_length = start.distance(waypoint);
+        if segment_length <= f32::EPSILON && remaining <= f32::EPSILON {
+            return waypoint;
+        }
+        if segment_length >= remaining {
+            return start + (waypoint - start) * (remaining / segment_length);
+        }
+        remaining -= segment_length;
+        start = waypoint;
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slows_down_within_slowing_radius() {
+        let config = PathfindingConfig::default();
+        let far = follow_path(Vec2::ZERO, None, &[Vec2::new(10.0, 0.0)], &config);
+        assert!(far.abs_diff_eq(Vec2::X, 1e-5));
+        let distance = config.slowing_radius / 2.0;
+        let near = follow_path(Vec2::ZERO, None, &[Vec2::new(distance, 0.0)], &config);
+        assert!(near.abs_diff_eq(Vec2::new(0.5, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn corner_slowdown_is_clamped_at_min_corner_speed() {
+        let config = PathfindingConfig::default();
+        let previous = Some(Vec2::new(-10.0, 0.0));
+        let corner = Vec2::ZERO;
+
+        // Turning back on itself at the corner.
+        let back = follow_path(corner, previous, &[corner, Vec2::new(-10.0, 0.0)], &config);
+        assert!((back.length() - config.min_corner_speed).abs() < 1e-5);
+        assert!(back.x < 0.0);
+
+        // A right angle halves the speed at the corner, and not at all far before it.
+        let waypoints = [corner, Vec2::new(0.0, 10.0)];
+        let turning = follow_path(corner, previous, &waypoints, &config);
+        assert!((turning.length() - 0.5).abs() < 1e-5);
+        let before = Vec2::new(-config.corner_slowing_radius, 0.0);
+        let approaching = follow_path(before, previous, &waypoints, &config);
+        assert!((approaching.length() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn no_lookahead_still_steers_to_the_next_waypoint() {
+        let config = PathfindingConfig {
+            lookahead_distance: 0.0,
+            ..default()
+        };
+        let waypoint = Vec2::new(10.0, 0.0);
+        for previous in [None, Some(Vec2::ZERO), Some(Vec2::new(-10.0, 0.0))] {
+            let vel = follow_path(Vec2::ZERO, previous, &[Vec2::ZERO, waypoint], &config);
+            assert!(vel.is_finite(), "{:?} from {:?}", vel, previous);
+            let vel = follow_path(Vec2::ZERO, previous, &[waypoint], &config);
+            assert!(
+                vel.is_finite() && vel.x > 0.0,
+                "{:?} from {:?}",
+                vel,
+                previous
+            );
+        }
+    }
+}
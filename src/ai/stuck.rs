@@ -0,0 +1,156 @@
+//! Detects people that stop getting closer to their target, whatever blocks them, and escalates
+//! from nudging them to replanning around the crowd and finally resolving the situation.
+
+use super::{Action, Actions, BuildPath, Target};
+use crate::{
+    config::{StuckConfig, StuckResolution},
+    person::Walker,
+    rng::SimRng,
+    simulation::SimClock,
+};
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+/// Remaining distance of a person at the start of the current progress window.
+#[derive(Component)]
+pub struct Progress {
+    window_start: f32,
+    /// Taken on the next step when `None`, once a new path has been built.
+    start_distance: Option<f32>,
+    stage: StuckStage,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StuckStage {
+    Moving,
+    Nudged,
+    Replanned,
+}
+
+/// Makes the next path built for a person go around the people close to it.
+#[derive(Component, Debug)]
+pub struct AvoidCrowd;
+
+/// Sent when a person made no progress even after being nudged and replanning.
+#[derive(Debug)]
+pub struct Stuck {
+    pub entity: Entity,
+    pub pos: Vec2,
+    pub target: Vec2,
+    pub resolution: StuckResolution,
+}
+
+/// Sent when a stuck person gives up its trip, right before it is despawned.
+#[derive(Debug)]
+pub struct GaveUp(pub Entity);
+
+type ProgressQuery<'a> = (
+    Entity,
+    &'a mut Transform,
+    &'a mut Velocity,
+    &'a mut ExternalImpulse,
+    &'a Target,
+    &'a Actions,
+    Option<&'a Walker>,
+    Option<&'a mut Progress>,
+);
+
+pub fn track_progress(
+    mut commands: Commands,
+    config: Res<StuckConfig>,
+    clock: Res<SimClock>,
+    mut rng: ResMut<SimRng>,
+    mut people: Query<ProgressQuery>,
+    mut stuck_events: EventWriter<Stuck>,
+    mut gave_up_events: EventWriter<GaveUp>,
+) {
+    let now = clock.elapsed_secs();
+    for (entity, mut transform, mut velocity, mut impulse, target, actions, walker, progress) in
+        people.iter_mut()
+    {
+        let pos = transform.translation.xy();
+        let waypoint = match actions.current() {
+            Some(Action::GoTo(waypoint)) => *waypoint,
+            _ => {
+                // Not walking, so not stuck either.
+                commands.entity(entity).remove::<Progress>();
+                continue;
+            }
+        };
+        let distance = actions.remaining_distance(pos);
+        let mut progress = match progress {
+            Some(progress) => progress,
+            None => {
+                commands.entity(entity).insert(Progress {
+                    window_start: now,
+                    start_distance: Some(distance),
+                    stage: StuckStage::Moving,
+                });
+                continue;
+            }
+        };
+        let start_distance = match progress.start_distance {
+            Some(start_distance) => start_distance,
+            None => {
+                progress.start_distance = Some(distance);
+                continue;
+            }
+        };
+
+        let patience = walker.copied().unwrap_or_default().patience;
+        if now - progress.window_start < config.progress_window * patience {
+            continue;
+        }
+        progress.window_start = now;
+        progress.start_distance = Some(distance);
+        if start_distance - distance >= config.min_progress {
+            progress.stage = StuckStage::Moving;
+            continue;
+        }
+
+        match progress.stage {
+            StuckStage::Moving => {
+                debug!("Nudging {:?}, stuck at {}", entity, pos);
+                let side = if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+                let dir = (waypoint - pos).normalize_or_zero().perp();
+                impulse.impulse += side * config.nudge_impulse * dir;
+                progress.stage = StuckStage::Nudged;
+            }
+            StuckStage::Nudged => {
+                info!(
+                    "Replanning around the crowd for {:?}, stuck at {}",
+                    entity, pos
+                );
+                commands.entity(entity).insert(BuildPath).insert(AvoidCrowd);
+                progress.start_distance = None;
+                progress.stage = StuckStage::Replanned;
+            }
+            StuckStage::Replanned => {
+                warn!(
+                    "{:?} is stuck at {} on its way to {}, resolving with {:?}",
+                    entity, pos, **target, config.resolution
+                );
+                stuck_events.send(Stuck {
+                    entity,
+                    pos,
+                    target: **target,
+                    resolution: config.resolution,
+                });
+                match config.resolution {
+                    StuckResolution::GiveUp => {
+                        gave_up_events.send(GaveUp(entity));
+                        commands.entity(entity).despawn();
+                    }
+                    StuckResolution::Teleport => {
+                        transform.translation.x = waypoint.x;
+                        transform.translation.y = waypoint.y;
+                        *velocity = Velocity::zero();
+                        progress.start_distance = None;
+                        progress.stage = StuckStage::Moving;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Riding the buses: people travelling alone plan a ride when it gets them to their door sooner
+//! than walking, wait for a bus at the stop and ride it to the stop closest to their door.
+
+use super::{group::Group, Action, Actions, BuildPath, Target, TargetDoor, WaitFor};
+use crate::{
+    config::{CrowdConfig, TransitConfig},
+    metrics::{SimMetrics, TripStart},
+    person::{self, Walker},
+    road::RoadGraph,
+    simulation::SimClock,
+    transit::TransitLine,
+    vehicle::bus::{self, Bus, Passenger},
+};
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashSet};
+
+/// A bus ride from the stop at `board` to the stop at `alight`.
+#[derive(Clone, Copy, Debug)]
+pub struct Ride {
+    pub board: Vec2,
+    pub alight: Vec2,
+}
+
+/// The ride on one of `lines` that gets from `from` to `to` the soonest, counting the walks to
+/// and from the stops and half the headway of waiting, if it is sooner than walking all the way
+/// at `walk_speed`. Only the stops buses of the line stand at are planned with, on lines buses
+/// can drive along.
+pub fn plan_ride<'a>(
+    lines: impl Iterator<Item = &'a TransitLine>,
+    graph: &RoadGraph,
+    config: &TransitConfig,
+    from: Vec2,
+    to: Vec2,
+    walk_speed: f32,
+) -> Option<Ride> {
+    let walk_time = from.distance(to) / walk_speed;
+    lines
+        .filter_map(|line| {
+            let (_, stops) = bus::line_route(graph, line)?;
+            let closest = |pos: Vec2| {
+                (0..stops.len()).min_by(|&a, &b| {
+                    stops[a]
+                        .0
+                        .distance(pos)
+                        .total_cmp(&stops[b].0.distance(pos))
+                })
+            };
+            let (board, alight) = (closest(from)?, closest(to)?);
+            if board >= alight {
+                return None;
+            }
+            let ((board_stop, board_distance), (alight_stop, alight_distance)) =
+                (stops[board], stops[alight]);
+            let time = (from.distance(board_stop) + alight_stop.distance(to)) / walk_speed
+                + line.headway / 2.0
+                + (alight_distance - board_distance) / config.planning_speed
+                + (alight - board - 1) as f32 * config.dwell_time;
+            Some((
+                time,
+                Ride {
+                    board: board_stop,
+                    alight: alight_stop,
+                },
+            ))
+        })
+        .filter(|&(time, _)| time < walk_time)
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, ride)| ride)
+}
+
+/// Seconds a person has been waiting at a stop for a bus going to theirs.
+#[derive(Component, Deref, Debug)]
+pub struct BusWait(f32);
+
+/// Makes the paths built for a person walk all the way, once it gave up waiting for a bus.
+#[derive(Component, Debug)]
+pub struct NoRide;
+
+type WaitingQuery<'a> = (
+    Entity,
+    &'a Actions,
+    Option<&'a Walker>,
+    Option<&'a mut BusWait>,
+);
+
+/// Sends the people who waited at their stop for longer than their patience allows to walk the
+/// rest of the way instead, as no bus going to their stop may ever come.
+pub fn give_up_waiting(
+    mut commands: Commands,
+    config: Res<TransitConfig>,
+    clock: Res<SimClock>,
+    mut people: Query<WaitingQuery, Without<Group>>,
+) {
+    let dt = clock.delta().as_secs_f32();
+    for (entity, actions, walker, bus_wait) in people.iter_mut() {
+        let waiting = matches!(actions.current(), Some(Action::Wait(WaitFor::Bus { .. })));
+        let patience = walker.copied().unwrap_or_default().patience * config.wait_patience;
+        let wait_time = bus_wait.as_ref().map_or(0.0, |bus_wait| bus_wait.0) + dt;
+        match bus_wait {
+            Some(_) if !waiting => {
+                commands.entity(entity).remove::<BusWait>();
+            }
+            None if !waiting => {}
+            _ if wait_time >= patience => {
+                info!("{:?} gave up waiting for a bus", entity);
+                commands
+                    .entity(entity)
+                    .insert(BuildPath)
+                    .insert(NoRide)
+                    .remove::<BusWait>();
+            }
+            Some(mut bus_wait) => bus_wait.0 = wait_time,
+            None => {
+                commands.entity(entity).insert(BusWait(wait_time));
+            }
+        }
+    }
+}
+
+type RiderQuery<'a> = (
+    Entity,
+    &'a Transform,
+    &'a Actions,
+    &'a Target,
+    Option<&'a TargetDoor>,
+    Option<&'a Walker>,
+    Option<&'a TripStart>,
+);
+
+/// Lets the passengers of the buses standing at a stop get off there, spawning them again with
+/// the rest of their plan, and the people waiting there for a bus going to their stop get on, as
+/// long as there is room. Buses at the end of their line let everyone off.
+#[allow(clippy::too_many_arguments)]
+pub fn board_and_alight(
+    mut commands: Commands,
+    config: Res<TransitConfig>,
+    crowd_config: Res<CrowdConfig>,
+    clock: Res<SimClock>,
+    mut metrics: ResMut<SimMetrics>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut buses: Query<(&mut Bus, &Transform)>,
+    riders: Query<RiderQuery, Without<Group>>,
+) {
+    let dt = clock.delta().as_secs_f32();
+    let waiting: Vec<_> = riders
+        .iter()
+        .filter_map(|rider| {
+            let (board, alight) = match rider.2.current() {
+                Some(Action::Wait(WaitFor::Bus { board, alight })) => (*board, *alight),
+                _ => return None,
+            };
+            Some((rider, board, alight))
+        })
+        .collect();
+    metrics.bus_wait_time += dt * waiting.len() as f32;
+
+    let mut boarded = HashSet::default();
+    for (mut bus, transform) in buses.iter_mut() {
+        let stop = match bus.standing_at() {
+            Some(stop) => stop,
+            None if bus.finished() => transform.translation.xy(),
+            None => continue,
+        };
+        let finished = bus.finished();
+        let (alighting, riding) = std::mem::take(&mut bus.passengers)
+            .into_iter()
+            .partition(|passenger| finished || passenger.alight.distance(stop) < 0.1);
+        bus.passengers = riding;
+        for mut passenger in alighting {
+            let person_entity = person::add_person(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &crowd_config,
+                stop,
+            );
+            let mut alighted = commands.entity(person_entity);
+            alighted.insert(Target(passenger.target));
+            if passenger.alight.distance(stop) < 0.1 {
+                passenger.actions.next();
+                alighted.insert(passenger.actions);
+            } else {
+                // Let off before their stop, at the end of the line.
+                alighted.insert(BuildPath);
+            }
+            if let Some(target_door) = passenger.target_door {
+                alighted.insert(TargetDoor(target_door));
+            }
+            if let Some(walker) = passenger.walker {
+                alighted.insert(walker);
+            }
+            if let Some(trip_start) = passenger.trip_start {
+                alighted.insert(TripStart(trip_start));
+            }
+        }
+
+        for &(rider, board, alight) in &waiting {
+            let (entity, rider_transform, actions, target, target_door, walker, trip_start) = rider;
+            if bus.passengers.len() >= config.capacity || finished {
+                break;
+            }
+            let at_stop = board.distance(stop) < 0.1
+                && rider_transform.translation.xy().distance(stop) < config.boarding_radius;
+            if !at_stop || !bus.goes_to(alight) || !boarded.insert(entity) {
+                continue;
+            }
+            bus.passengers.push(Passenger {
+                alight,
+                walker: walker.copied(),
+                trip_start: trip_start.map(|trip_start| **trip_start),
+                target: **target,
+                target_door: target_door.map(|target_door| **target_door),
+                actions: actions.clone(),
+            });
+            metrics.bus_rides += 1;
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -1,15 +1,65 @@
+use crate::ai::InvalidatePaths;
 use bevy::prelude::*;
-use bevy_prototype_lyon::prelude::{FillMode, *};
+use bevy_prototype_lyon::{
+    entity::ShapeBundle,
+    prelude::{FillMode, *},
+};
 use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Component)]
+#[derive(Component, Clone, Serialize, Deserialize)]
 pub struct Building {
     pub size: Vec2,
     pub pos: Vec2,
     pub doors: Vec<Door>,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Building {
+    pub fn contains(&self, point: Vec2) -> bool {
+        let local = (point - self.pos).abs();
+        local.x <= self.size.x / 2.0 && local.y <= self.size.y / 2.0
+    }
+
+    /// Returns the side closest to `point`, the door position along it and the distance to it.
+    pub fn closest_side(&self, point: Vec2) -> (Side, f32, f32) {
+        let local = point - self.pos;
+        let half_size = self.size / 2.0;
+        let along_x = (local.x / half_size.x).clamp(-1.0, 1.0);
+        let along_y = (local.y / half_size.y).clamp(-1.0, 1.0);
+        [
+            (Side::Left, along_y, (local.x + half_size.x).abs()),
+            (Side::Right, along_y, (local.x - half_size.x).abs()),
+            (Side::Bottom, along_x, (local.y + half_size.y).abs()),
+            (Side::Top, along_x, (local.y - half_size.y).abs()),
+        ]
+        .into_iter()
+        .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+        .unwrap()
+    }
+
+    pub fn door_pos(&self, door: &Door) -> Vec2 {
+        self.pos + door.side.get_pos(self.size, door.pos)
+    }
+
+    /// Returns the walls of the building as segments, counterclockwise.
+    pub fn edges(&self) -> [(Vec2, Vec2); 4] {
+        let half_size = self.size / 2.0;
+        let corners = [
+            self.pos + Vec2::new(-half_size.x, -half_size.y),
+            self.pos + Vec2::new(half_size.x, -half_size.y),
+            self.pos + Vec2::new(half_size.x, half_size.y),
+            self.pos + Vec2::new(-half_size.x, half_size.y),
+        ];
+        [
+            (corners[0], corners[1]),
+            (corners[1], corners[2]),
+            (corners[2], corners[3]),
+            (corners[3], corners[0]),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Side {
     Left,
     Right,
@@ -37,7 +87,7 @@ impl Side {
     }
 }
 
-#[derive(Component, Clone, Copy, Debug)]
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Door {
     pub side: Side,
     pos: f32,
@@ -63,27 +113,74 @@ pub fn on_add_building(
     added_building: Query<(Entity, &Building), Added<Building>>,
 ) {
     for (building_entity, building) in added_building.iter() {
-        let square = shapes::Rectangle {
-            extents: building.size,
-            origin: RectangleOrigin::Center,
-        };
         commands
             .entity(building_entity)
             .insert(RigidBody::Fixed)
-            .insert(Collider::cuboid(
-                building.size.x / 2.0,
-                building.size.y / 2.0,
-            ))
-            .insert_bundle(GeometryBuilder::build_as(
-                &square,
-                DrawMode::Fill(FillMode::color(Color::GRAY)),
-                Transform::from_xyz(building.pos.x, building.pos.y, 0.0),
-            ));
+            .insert(building_collider(building))
+            .insert_bundle(building_shape(building));
+
+        draw_doors(&mut commands, building_entity, building);
+    }
+}
+
+type ChangedBuildingQuery<'a> = (
+    Entity,
+    &'a Building,
+    ChangeTrackers<Building>,
+    Option<&'a Children>,
+);
+
+pub fn on_change_building(
+    mut commands: Commands,
+    changed_building: Query<ChangedBuildingQuery, Changed<Building>>,
+    doors: Query<(), With<Door>>,
+    mut invalidate_paths: EventWriter<InvalidatePaths>,
+) {
+    for (building_entity, building, tracker, children) in changed_building.iter() {
+        if tracker.is_added() {
+            continue;
+        }
+        commands
+            .entity(building_entity)
+            .insert(building_collider(building))
+            .insert_bundle(building_shape(building));
 
+        for &child in children.into_iter().flat_map(|children| children.iter()) {
+            if doors.get(child).is_ok() {
+                commands.entity(child).despawn_recursive();
+            }
+        }
         draw_doors(&mut commands, building_entity, building);
+
+        invalidate_paths.send(InvalidatePaths);
     }
 }
 
+pub fn on_remove_building(
+    removed_buildings: RemovedComponents<Building>,
+    mut invalidate_paths: EventWriter<InvalidatePaths>,
+) {
+    if removed_buildings.iter().next().is_some() {
+        invalidate_paths.send(InvalidatePaths);
+    }
+}
+
+fn building_collider(building: &Building) -> Collider {
+    Collider::cuboid(building.size.x / 2.0, building.size.y / 2.0)
+}
+
+fn building_shape(building: &Building) -> ShapeBundle {
+    let square = shapes::Rectangle {
+        extents: building.size,
+        origin: RectangleOrigin::Center,
+    };
+    GeometryBuilder::build_as(
+        &square,
+        DrawMode::Fill(FillMode::color(Color::GRAY)),
+        Transform::from_xyz(building.pos.x, building.pos.y, 0.0),
+    )
+}
+
 fn draw_doors(commands: &mut Commands, building_entity: Entity, building: &Building) {
     for door in &building.doors {
         let pos = door.side.get_pos(building.size, door.pos);
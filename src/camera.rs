@@ -19,6 +19,19 @@ pub fn setup(mut commands: Commands) {
         .insert(GameCamera);
 }
 
+pub fn cursor_world_pos(
+    windows: &Windows,
+    camera: &Camera,
+    camera_transform: &GlobalTransform,
+) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor_pos = window.cursor_position()?;
+    let window_size = Vec2::new(window.width(), window.height());
+    let ndc = 2.0 * (cursor_pos / window_size) - Vec2::ONE;
+    let ndc_to_world = camera_transform.compute_matrix() * camera.projection_matrix().inverse();
+    Some(ndc_to_world.project_point3(ndc.extend(-1.0)).truncate())
+}
+
 pub fn follow_player(
     mut camera: Query<&mut Transform, With<GameCamera>>,
     player: Query<&Transform, (With<Player>, Without<GameCamera>)>,
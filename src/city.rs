@@ -0,0 +1,158 @@
+use crate::{ai::InvalidatePaths, building::*, level::*, road::*, transit::TransitLine};
+use bevy::prelude::*;
+
+/// Roads and buildings, loaded from a level file or the default city.
+pub struct CityPlugin;
+
+#[derive(SystemLabel)]
+pub enum CityLabel {
+    Update,
+    RoadGraph,
+    Cleanup,
+}
+
+pub struct CityConfig {
+    /// Level to load on startup, falling back to the default city when it can not be read.
+    pub level_path: Option<String>,
+}
+
+impl Default for CityConfig {
+    fn default() -> Self {
+        Self {
+            level_path: Some(LEVEL_PATH.to_string()),
+        }
+    }
+}
+
+impl Plugin for CityPlugin {
+    fn build(&self, app: &mut App) {
+        crate::add_event_once::<InvalidatePaths>(app);
+        app.init_resource::<CityConfig>()
+            .init_resource::<RoadGraph>()
+            .add_startup_system(setup)
+            .add_system_set(
+                SystemSet::new()
+                    .label(CityLabel::Update)
+                    .with_system(on_add_road)
+                    .with_system(on_change_road)
+                    .with_system(on_change_road_node)
+                    .with_system(on_add_building)
+                    .with_system(on_change_building),
+            )
+            .add_system(update_road_graph.label(CityLabel::RoadGraph))
+            .add_system(draw_junctions.after(CityLabel::RoadGraph))
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .label(CityLabel::Cleanup)
+                    .with_system(on_remove_road_node)
+                    .with_system(on_remove_building),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, config: Res<CityConfig>) {
+    if let Some(level_path) = &config.level_path {
+        match Level::load(level_path) {
+            Ok(level) => {
+                level.spawn(&mut commands);
+                return;
+            }
+            Err(err) => info!(
+                "Could not load {}: {}, using default level",
+                level_path, err
+            ),
+        }
+    }
+    add_roads(&mut commands);
+    add_buildings(&mut commands);
+}
+
+pub fn add_roads(commands: &mut Commands) {
+    let node_a = commands
+        .spawn()
+        .insert(RoadNode {
+            pos: Vec2::new(-100.0, 100.0),
+        })
+        .id();
+    let node_b = commands
+        .spawn()
+        .insert(RoadNode {
+            pos: Vec2::new(-100.0, -100.0),
+        })
+        .id();
+    let node_c = commands
+        .spawn()
+        .insert(RoadNode {
+            pos: Vec2::new(100.0, -100.0),
+        })
+        .id();
+    let node_d = commands
+        .spawn()
+        .insert(RoadNode {
+            pos: Vec2::new(100.0, 100.0),
+        })
+        .id();
+
+    commands.spawn().insert(Road::new(node_a, node_b));
+    commands.spawn().insert(Road::new(node_b, node_c));
+    commands.spawn().insert(Road::new(node_c, node_d));
+    commands.spawn().insert(Road::new(node_d, node_a));
+
+    // A bus line around the block, counterclockwise, stopping in the middle of every side.
+    commands.spawn().insert(TransitLine {
+        nodes: vec![node_d, node_c, node_b, node_a, node_d],
+        stops: vec![
+            Vec2::new(88.0, 0.0),
+            Vec2::new(0.0, -88.0),
+            Vec2::new(-88.0, 0.0),
+            Vec2::new(0.0, 88.0),
+        ],
+        headway: 40.0,
+    });
+}
+
+pub fn add_buildings(commands: &mut Commands) {
+    commands.spawn().insert(Building {
+        pos: Vec2::new(-50.0, -50.0),
+        size: Vec2::new(50.0, 50.0),
+        doors: vec![Door::new(Side::Left, 0.0)],
+    });
+    commands.spawn().insert(Building {
+        pos: Vec2::new(50.0, -50.0),
+        size: Vec2::new(50.0, 50.0),
+        doors: vec![Door::new(Side::Bottom, 0.0)],
+    });
+    commands.spawn().insert(Building {
+        pos: Vec2::new(50.0, 50.0),
+        size: Vec2::new(50.0, 50.0),
+        doors: vec![Door::new(Side::Top, 0.5)],
+    });
+    commands.spawn().insert(Building {
+        pos: Vec2::new(-50.0, 50.0),
+        size: Vec2::new(50.0, 50.0),
+        doors: vec![Door::new(Side::Left, 0.5)],
+    });
+
+    commands.spawn().insert(Building {
+        pos: Vec2::new(-50.0, 0.0),
+        size: Vec2::new(50.0, 30.0),
+        doors: vec![Door::new(Side::Right, 0.0)],
+    });
+    commands.spawn().insert(Building {
+        pos: Vec2::new(50.0, 0.0),
+        size: Vec2::new(50.0, 30.0),
+        doors: vec![Door::new(Side::Right, 0.0)],
+    });
+
+    commands.spawn().insert(Building {
+        pos: Vec2::new(0.0, 50.0),
+        size: Vec2::new(30.0, 50.0),
+        doors: vec![Door::new(Side::Bottom, 0.5)],
+    });
+    commands.spawn().insert(Building {
+        pos: Vec2::new(0.0, -50.0),
+        size: Vec2::new(30.0, 50.0),
+        doors: vec![],
+    });
+}
@@ -0,0 +1,930 @@
+use crate::{level::read_ron, person::Walker};
+use bevy::prelude::*;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, path::Path};
+
+/// Physical body of every person.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrowdConfig {
+    pub half_size: f32,
+    pub mass: f32,
+    pub linear_damping: f32,
+}
+
+impl Default for CrowdConfig {
+    fn default() -> Self {
+        Self {
+            half_size: 0.5,
+            mass: 60.0,
+            linear_damping: 0.99,
+        }
+    }
+}
+
+/// How people avoid each other and the walls while walking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MovementModel {
+    /// Sidestep the first person ahead and keep away from what the personal space rays hit.
+    Rays,
+    /// Optimal reciprocal collision avoidance, see `person::orca`.
+    Orca,
+    /// Helbing's social force model, see `person::social_force`.
+    SocialForce,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MovementConfig {
+    pub model: MovementModel,
+    /// Sidestep people that are in the walking direction.
+    pub collision_avoidance: bool,
+    /// Keep some distance from nearby people and walls.
+    pub personal_space: bool,
+    /// Impulse applied against the velocity, per unit of speed, while standing.
+    pub stop_gain: f32,
+    /// How far ahead people look for others to sidestep.
+    pub avoidance_distance: f32,
+    pub personal_space_rays: usize,
+    pub personal_space_distance: f32,
+    pub orca: OrcaConfig,
+    pub social_force: SocialForceConfig,
+    pub density: DensityConfig,
+}
+
+impl Default for MovementConfig {
+    fn default() -> Self {
+        Self {
+            model: MovementModel::Rays,
+            collision_avoidance: true,
+            personal_space: true,
+            stop_gain: 20.0,
+            avoidance_distance: 20.0,
+            personal_space_rays: 6,
+            personal_space_distance: 5.0,
+            orca: OrcaConfig::default(),
+            social_force: SocialForceConfig::default(),
+            density: DensityConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OrcaConfig {
+    /// Radius of the circle standing for a person.
+    pub radius: f32,
+    /// Distance within which people and walls are taken into account.
+    pub neighbour_distance: f32,
+    pub max_neighbours: usize,
+    /// Seconds ahead for which the chosen velocity avoids other people.
+    pub time_horizon: f32,
+    /// Seconds ahead for which the chosen velocity avoids walls.
+    pub obstacle_time_horizon: f32,
+}
+
+impl Default for OrcaConfig {
+    fn default() -> Self {
+        Self {
+            radius: 0.75,
+            neighbour_distance: 15.0,
+            max_neighbours: 10,
+            time_horizon: 2.0,
+            obstacle_time_horizon: 0.5,
+        }
+    }
+}
+
+/// Forces are given per unit of mass, as accelerations.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocialForceConfig {
+    /// Radius of the circle standing for a person.
+    pub radius: f32,
+    /// Distance within which people and walls exert a force.
+    pub interaction_distance: f32,
+    /// Seconds it takes to get back to the desired velocity.
+    pub relaxation_time: f32,
+    /// Repulsion between two people touching each other.
+    pub person_repulsion: f32,
+    /// Distance over which the repulsion between people decays by a factor e.
+    pub person_range: f32,
+    /// Weight of the repulsion from people behind, 1 making it as strong as from people ahead.
+    pub anisotropy: f32,
+    /// Repulsion from a wall touched by a person.
+    pub wall_repulsion: f32,
+    /// Distance over which the repulsion from walls decays by a factor e.
+    pub wall_range: f32,
+    /// Pull people walking in a group towards its center.
+    pub group_cohesion: bool,
+    /// Attraction per unit of distance to the center of the group.
+    pub cohesion_strength: f32,
+}
+
+impl Default for SocialForceConfig {
+    fn default() -> Self {
+        Self {
+            radius: 0.6,
+            interaction_distance: 10.0,
+            relaxation_time: 0.5,
+            person_repulsion: 100.0,
+            person_range: 1.0,
+            anisotropy: 0.5,
+            wall_repulsion: 100.0,
+            wall_range: 0.5,
+            group_cohesion: true,
+            cohesion_strength: 2.0,
+        }
+    }
+}
+
+/// How the local density of the crowd slows people down.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DensityConfig {
+    pub enabled: bool,
+    /// Radius of the circle people are counted in.
+    pub radius: f32,
+    pub curve: SpeedDensityCurve,
+    /// Fraction of the preferred speed kept at jam density, so jams can still dissolve.
+    pub min_speed_factor: f32,
+}
+
+impl Default for DensityConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 3.0,
+            // Weidmann's values, scaled so that the jam density packs people shoulder to shoulder.
+            curve: SpeedDensityCurve::Weidmann {
+                gamma: 0.354,
+                jam_density: 1.0,
+            },
+            min_speed_factor: 0.1,
+        }
+    }
+}
+
+impl DensityConfig {
+    /// Fraction of the preferred speed people walk at in a crowd of `density` people per square
+    /// unit.
+    pub fn speed_factor(&self, density: f32) -> f32 {
+        if !self.enabled {
+            return 1.0;
+        }
+        self.curve.speed_factor(density).max(self.min_speed_factor)
+    }
+}
+
+/// Speed–density relation, or fundamental diagram, of pedestrians.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum SpeedDensityCurve {
+    /// `1 - exp(-gamma * (1 / density - 1 / jam_density))`, fitted by Weidmann on measurements.
+    Weidmann { gamma: f32, jam_density: f32 },
+    /// Decreases linearly from the free speed at no density to zero at the jam density.
+    Linear { jam_density: f32 },
+}
+
+impl SpeedDensityCurve {
+    pub fn speed_factor(&self, density: f32) -> f32 {
+        if density <= 0.0 {
+            return 1.0;
+        }
+        let factor = match *self {
+            Self::Weidmann { gamma, jam_density } => {
+                1.0 - (-gamma * (1.0 / density - 1.0 / jam_density)).exp()
+            }
+            Self::Linear { jam_density } => 1.0 - density / jam_density,
+        };
+        factor.clamp(0.0, 1.0)
+    }
+}
+
+/// Distributions the walking traits of spawned people are drawn from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalkerConfig {
+    pub preferred_speed: TraitDistribution,
+    pub max_accel: TraitDistribution,
+    pub personal_space: TraitDistribution,
+    pub patience: TraitDistribution,
+}
+
+impl Default for WalkerConfig {
+    fn default() -> Self {
+        Self {
+            preferred_speed: TraitDistribution::Normal {
+                mean: 10.0,
+                std_dev: 1.5,
+                min: 5.0,
+                max: 15.0,
+            },
+            max_accel: TraitDistribution::Uniform {
+                min: 8.0,
+                max: 14.0,
+            },
+            personal_space: TraitDistribution::Uniform { min: 0.0, max: 0.5 },
+            patience: TraitDistribution::Uniform { min: 0.5, max: 3.0 },
+        }
+    }
+}
+
+impl WalkerConfig {
+    pub fn sample(&self, rng: &mut impl Rng) -> Walker {
+        Walker {
+            preferred_speed: self.preferred_speed.sample(rng),
+            max_accel: self.max_accel.sample(rng),
+            personal_space: self.personal_space.sample(rng),
+            patience: self.patience.sample(rng),
+        }
+    }
+
+    /// Rejects distributions that give negative traits or have their bounds out of order.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, distribution) in [
+            ("preferred_speed", &self.preferred_speed),
+            ("max_accel", &self.max_accel),
+            ("personal_space", &self.personal_space),
+            ("patience", &self.patience),
+        ] {
+            distribution
+                .validate(0.0)
+                .map_err(|err| format!("walkers.{}: {}", name, err))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum TraitDistribution {
+    Constant(f32),
+    Uniform {
+        min: f32,
+        max: f32,
+    },
+    /// Normal distribution, clamped so the tails cannot give absurd values.
+    Normal {
+        mean: f32,
+        std_dev: f32,
+        min: f32,
+        max: f32,
+    },
+}
+
+impl TraitDistribution {
+    /// Draws a value, within the bounds even when they are swapped.
+    pub fn sample(&self, rng: &mut impl Rng) -> f32 {
+        match *self {
+            Self::Constant(value) => value,
+            Self::Uniform { min, max } => {
+                let (min, max) = (min.min(max), min.max(max));
+                if min < max {
+                    rng.gen_range(min..max)
+                } else {
+                    min
+                }
+            }
+            Self::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                let value = match Normal::new(mean, std_dev) {
+                    Ok(normal) => normal.sample(rng),
+                    Err(_) => mean,
+                };
+                value.max(min.min(max)).min(min.max(max))
+            }
+        }
+    }
+
+    /// Checks that the bounds are numbers, in order and not below `lowest`.
+    fn validate(&self, lowest: f32) -> Result<(), String> {
+        let (min, max) = match *self {
+            Self::Constant(value) => (value, value),
+            Self::Uniform { min, max } => (min, max),
+            Self::Normal {
+                mean,
+                std_dev,
+                min,
+                max,
+            } => {
+                if !(mean.is_finite() && std_dev.is_finite() && std_dev >= 0.0) {
+                    return Err(format!("invalid mean {} or deviation {}", mean, std_dev));
+                }
+                (min, max)
+            }
+        };
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(format!("invalid bounds {} to {}", min, max));
+        }
+        if min < lowest {
+            return Err(format!("values down to {}, below {}", min, lowest));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathfindingConfig {
+    /// Removes the waypoints that can be skipped without hitting an obstacle.
+    pub simplify_paths: bool,
+    /// Half size of the box that must fit between obstacles along a path.
+    pub clearance: f32,
+    /// Distance between neighbouring nodes of the search grid.
+    pub grid_step: f32,
+    /// Distance to the target at which the search stops. It is never less than three quarters of
+    /// `grid_step`, so the grid always has a node close enough.
+    pub goal_radius: f32,
+    /// Grid nodes the search expands before giving up on a target it can not reach.
+    pub max_expansions: usize,
+    /// Distance to the last waypoint at which a person has arrived.
+    pub arrival_radius: f32,
+    /// Distance along the path to the point people steer towards.
+    pub lookahead_distance: f32,
+    /// Distance from the end of the path at which people start slowing down.
+    pub slowing_radius: f32,
+    /// Distance from a corner at which people start slowing down for it.
+    pub corner_slowing_radius: f32,
+    /// Fraction of the preferred speed people keep when turning back on themselves.
+    pub min_corner_speed: f32,
+}
+
+impl Default for PathfindingConfig {
+    fn default() -> Self {
+        Self {
+            simplify_paths: true,
+            clearance: 0.5,
+            grid_step: 1.0,
+            goal_radius: 0.8,
+            max_expansions: 100_000,
+            arrival_radius: 0.5,
+            lookahead_distance: 3.0,
+            slowing_radius: 4.0,
+            corner_slowing_radius: 3.0,
+            min_corner_speed: 0.3,
+        }
+    }
+}
+
+/// Live crowd density read by the pathfinder, see `ai::congestion`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CongestionConfig {
+    pub enabled: bool,
+    /// Side of the square cells the density is measured over.
+    pub cell_size: f32,
+    /// Seconds over which the density of a cell follows the people in it.
+    pub smoothing_time: f32,
+    /// Extra cost of a step onto a path node per unit of density around it, on top of the length
+    /// of the step.
+    pub cost: f32,
+    /// Density above which a place counts as congested, so paths are not shortened through it and
+    /// people whose path goes through it look for another one.
+    pub congested_density: f32,
+    /// Seconds between two checks for people heading into congestion.
+    pub reroute_interval: f32,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cell_size: 5.0,
+            smoothing_time: 2.0,
+            cost: 20.0,
+            congested_density: 0.5,
+            reroute_interval: 5.0,
+        }
+    }
+}
+
+/// Detection of people that stop making progress, see `ai::stuck`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StuckConfig {
+    /// Seconds over which progress is measured, multiplied by the patience of the person.
+    pub progress_window: f32,
+    /// Distance a person must get closer to its target within a window.
+    pub min_progress: f32,
+    /// Sideways impulse given to a person first found stuck.
+    pub nudge_impulse: f32,
+    /// Distance within which people are avoided when replanning.
+    pub crowd_distance: f32,
+    /// Distance to avoided people below which path nodes get more expensive.
+    pub soft_obstacle_radius: f32,
+    /// Extra cost of a step onto a path node per avoided person close to it, on top of the length
+    /// of the step.
+    pub soft_obstacle_cost: f32,
+    pub resolution: StuckResolution,
+}
+
+impl Default for StuckConfig {
+    fn default() -> Self {
+        Self {
+            progress_window: 2.0,
+            min_progress: 1.0,
+            nudge_impulse: 20.0,
+            crowd_distance: 10.0,
+            soft_obstacle_radius: 1.5,
+            soft_obstacle_cost: 5.0,
+            resolution: StuckResolution::Teleport,
+        }
+    }
+}
+
+/// What happens to a person still stuck after replanning.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StuckResolution {
+    /// The person abandons its trip and disappears.
+    GiveUp,
+    /// The person is moved to the waypoint it could not reach.
+    Teleport,
+}
+
+/// Queues in front of the doors people enter buildings through, see `ai::queue`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoorConfig {
+    /// People going through a door per second.
+    pub capacity: f32,
+    /// Distance between two people waiting in line.
+    pub slot_spacing: f32,
+    /// Distance left to walk at which people join the queue of their door.
+    pub join_distance: f32,
+    /// Distance to the front of the queue under which the first person may go in.
+    pub slot_radius: f32,
+}
+
+impl Default for DoorConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 1.0,
+            slot_spacing: 1.5,
+            join_distance: 15.0,
+            slot_radius: 1.0,
+        }
+    }
+}
+
+/// People walking together, see `ai::group`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupConfig {
+    /// Probability for a spawn to be a group rather than a single person.
+    pub probability: f32,
+    /// Largest group spawned, groups having between two people and this.
+    pub max_size: usize,
+    pub formation: Formation,
+    /// Distance between two neighbours in the formation.
+    pub spacing: f32,
+    /// Speed correction towards the place in the formation, relative to the preferred speed, per
+    /// unit of distance.
+    pub formation_gain: f32,
+    /// Distance to a waypoint within which members wait for the rest of the group.
+    pub gather_radius: f32,
+    /// Distance to the rest of the group beyond which a member goes on alone.
+    pub split_distance: f32,
+}
+
+impl Default for GroupConfig {
+    fn default() -> Self {
+        Self {
+            probability: 0.3,
+            max_size: 4,
+            formation: Formation::Abreast,
+            spacing: 1.5,
+            formation_gain: 0.3,
+            gather_radius: 4.0,
+            split_distance: 15.0,
+        }
+    }
+}
+
+/// How the members of a group place themselves while walking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Formation {
+    /// Side by side, across the walking direction.
+    Abreast,
+    /// In single file behind the first member.
+    LeaderFollower,
+}
+
+/// Evacuation scenario, see `evacuation`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvacuationConfig {
+    /// Simulated seconds after which the evacuation starts on its own, if any. It can always be
+    /// started by hand with F6.
+    pub start_after: Option<f32>,
+    /// Safe places people head to.
+    pub exits: Vec<ExitZone>,
+    /// People inside every building, leaving through its doors at the door capacity.
+    pub occupants_per_building: u32,
+    /// Extra distance an exit counts as per person already heading to it.
+    pub congestion_weight: f32,
+    pub panic: PanicConfig,
+}
+
+impl Default for EvacuationConfig {
+    fn default() -> Self {
+        Self {
+            start_after: None,
+            exits: vec![
+                ExitZone::new(Vec2::new(-140.0, 0.0), 10.0),
+                ExitZone::new(Vec2::new(140.0, 0.0), 10.0),
+                ExitZone::new(Vec2::new(0.0, -140.0), 10.0),
+                ExitZone::new(Vec2::new(0.0, 140.0), 10.0),
+            ],
+            occupants_per_building: 20,
+            congestion_weight: 1.0,
+            panic: PanicConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ExitZone {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+impl ExitZone {
+    pub fn new(pos: Vec2, radius: f32) -> Self {
+        Self { pos, radius }
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.pos.distance(point) <= self.radius
+    }
+}
+
+/// How the walking traits of people change once they evacuate.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanicConfig {
+    pub speed_factor: f32,
+    pub personal_space_factor: f32,
+    pub patience_factor: f32,
+}
+
+impl Default for PanicConfig {
+    fn default() -> Self {
+        Self {
+            speed_factor: 1.3,
+            personal_space_factor: 0.2,
+            patience_factor: 0.5,
+        }
+    }
+}
+
+impl PanicConfig {
+    pub fn apply(&self, walker: Walker) -> Walker {
+        Walker {
+            preferred_speed: walker.preferred_speed * self.speed_factor,
+            max_accel: walker.max_accel,
+            personal_space: walker.personal_space * self.personal_space_factor,
+            patience: walker.patience * self.patience_factor,
+        }
+    }
+}
+
+/// Cars driving on the roads, see `vehicle`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VehicleConfig {
+    /// Number of vehicles kept driving around.
+    pub count: usize,
+    pub length: f32,
+    pub width: f32,
+    /// Speed vehicles slow down to before turning at an intersection.
+    pub turn_speed: f32,
+    /// Sideways acceleration vehicles keep to in curves, which sets how fast they take them.
+    pub lateral_accel: f32,
+    /// Distance ahead within which vehicles react to other vehicles, people and turns.
+    pub lookahead_distance: f32,
+    /// Extra distance around vehicles within which people wait for them to pass.
+    pub yield_margin: f32,
+    pub idm: IdmConfig,
+}
+
+impl Default for VehicleConfig {
+    fn default() -> Self {
+        Self {
+            count: 8,
+            length: 4.5,
+            width: 2.0,
+            turn_speed: 8.0,
+            lateral_accel: 4.0,
+            lookahead_distance: 60.0,
+            yield_margin: 1.5,
+            idm: IdmConfig::default(),
+        }
+    }
+}
+
+/// Parameters of the intelligent driver model.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdmConfig {
+    /// Speed on a free road, unless the speed limit is lower.
+    pub desired_speed: f32,
+    /// Time gap to the vehicle ahead at constant speed, in seconds.
+    pub time_headway: f32,
+    /// Gap to the vehicle ahead when stopped.
+    pub min_gap: f32,
+    pub max_accel: f32,
+    pub comfortable_decel: f32,
+    /// How quickly the acceleration drops when getting close to the desired speed.
+    pub accel_exponent: f32,
+}
+
+impl Default for IdmConfig {
+    fn default() -> Self {
+        Self {
+            desired_speed: 25.0,
+            time_headway: 1.2,
+            min_gap: 2.0,
+            max_accel: 6.0,
+            comfortable_decel: 8.0,
+            accel_exponent: 4.0,
+        }
+    }
+}
+
+/// Traffic signals at the intersections, see `signal`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignalConfig {
+    /// Without signals, people walk across the roads anywhere and vehicles never stop.
+    pub enabled: bool,
+    /// Seconds every road of an intersection gets green, one road after the other.
+    pub green_time: f32,
+    /// Seconds all the crosswalks of an intersection get the walk signal, while vehicles wait.
+    pub walk_time: f32,
+    /// Seconds everything is red between two phases, to clear the intersection.
+    pub clearance_time: f32,
+    /// Width of the crosswalks, along the road.
+    pub crosswalk_width: f32,
+    /// Distance from the road where people wait for the walk signal.
+    pub kerb_margin: f32,
+}
+
+impl Default for SignalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            green_time: 15.0,
+            walk_time: 10.0,
+            clearance_time: 3.0,
+            crosswalk_width: 4.0,
+            kerb_margin: 1.0,
+        }
+    }
+}
+
+/// Buses running along the transit lines, see `transit`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransitConfig {
+    /// Without transit, no buses run and everyone walks the whole way.
+    pub enabled: bool,
+    pub bus_length: f32,
+    pub bus_width: f32,
+    /// People a bus carries at most.
+    pub capacity: usize,
+    /// Seconds buses stand at every stop for people to get on and off.
+    pub dwell_time: f32,
+    /// Distance from a stop within which waiting people get on.
+    pub boarding_radius: f32,
+    /// Mean speed of the buses between two stops, to estimate how long a ride takes when
+    /// planning a trip.
+    pub planning_speed: f32,
+    /// Seconds people wait at a stop for a bus going to theirs, multiplied by their patience,
+    /// before walking the rest of the way instead.
+    pub wait_patience: f32,
+}
+
+impl Default for TransitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bus_length: 12.0,
+            bus_width: 2.5,
+            capacity: 40,
+            dwell_time: 8.0,
+            boarding_radius: 6.0,
+            planning_speed: 15.0,
+            wait_patience: 60.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpawnConfig {
+    pub enabled: bool,
+    /// Seconds between two spawned people.
+    pub interval: f32,
+    /// Distance in front of a door where people appear and head to.
+    pub door_distance: f32,
+    /// CSV file of trips per hour between doors and zones to spawn people from, see `od_matrix`.
+    /// Without one, people go between two random doors every `interval`.
+    pub od_matrix: Option<String>,
+    /// Areas the origin-destination matrix may name instead of single doors.
+    pub zones: Vec<OdZone>,
+}
+
+impl Default for SpawnConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 1.0,
+            door_distance: 2.0,
+            od_matrix: None,
+            zones: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OdZone {
+    pub name: String,
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+impl OdZone {
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.pos.distance(point) <= self.radius
+    }
+}
+
+/// All simulation configs, as read from a config file. Missing fields keep their default value.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    pub crowd: CrowdConfig,
+    pub movement: MovementConfig,
+    pub walkers: WalkerConfig,
+    pub pathfinding: PathfindingConfig,
+    pub congestion: CongestionConfig,
+    pub stuck: StuckConfig,
+    pub doors: DoorConfig,
+    pub groups: GroupConfig,
+    pub evacuation: EvacuationConfig,
+    pub vehicles: VehicleConfig,
+    pub signals: SignalConfig,
+    pub transit: TransitConfig,
+    pub spawn: SpawnConfig,
+}
+
+impl ConfigFile {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let config: Self = read_ron(path)?;
+        config.walkers.validate()?;
+        Ok(config)
+    }
+
+    /// Inserts the configs as resources, to be done before adding the simulation plugins.
+    pub fn insert_into(self, app: &mut App) {
+        app.insert_resource(self.crowd)
+            .insert_resource(self.movement)
+            .insert_resource(self.walkers)
+            .insert_resource(self.pathfinding)
+            .insert_resource(self.congestion)
+            .insert_resource(self.stuck)
+            .insert_resource(self.doors)
+            .insert_resource(self.groups)
+            .insert_resource(self.evacuation)
+            .insert_resource(self.vehicles)
+            .insert_resource(self.signals)
+            .insert_resource(self.transit)
+            .insert_resource(self.spawn);
+    }
+}
+
+/// Reads the config file path from `--config <path>`.
+pub fn config_path_from_args() -> Option<String> {
+    std::env::args().skip_while(|arg| arg != "--config").nth(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn samples(distribution: TraitDistribution) -> Vec<f32> {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        (0..1000).map(|_| distribution.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn samples_stay_within_bounds() {
+        assert!(samples(TraitDistribution::Constant(2.0))
+            .iter()
+            .all(|&value| value == 2.0));
+
+        let uniform = samples(TraitDistribution::Uniform { min: 1.0, max: 3.0 });
+        assert!(uniform.iter().all(|&value| (1.0..3.0).contains(&value)));
+        let swapped = samples(TraitDistribution::Uniform { min: 3.0, max: 1.0 });
+        assert!(swapped.iter().all(|&value| (1.0..3.0).contains(&value)));
+        let empty = samples(TraitDistribution::Uniform { min: 2.0, max: 2.0 });
+        assert!(empty.iter().all(|&value| value == 2.0));
+
+        let normal = samples(TraitDistribution::Normal {
+            mean: 10.0,
+            std_dev: 5.0,
+            min: 8.0,
+            max: 12.0,
+        });
+        assert!(normal.iter().all(|&value| (8.0..=12.0).contains(&value)));
+        assert!(normal.contains(&8.0) && normal.contains(&12.0));
+        let swapped = samples(TraitDistribution::Normal {
+            mean: 10.0,
+            std_dev: 5.0,
+            min: 12.0,
+            max: 8.0,
+        });
+        assert!(swapped.iter().all(|&value| (8.0..=12.0).contains(&value)));
+        let nan_bound = samples(TraitDistribution::Normal {
+            mean: 10.0,
+            std_dev: 5.0,
+            min: f32::NAN,
+            max: 12.0,
+        });
+        assert!(nan_bound.iter().all(|&value| value <= 12.0));
+    }
+
+    #[test]
+    fn walker_config_rejects_bad_bounds() {
+        assert!(WalkerConfig::default().validate().is_ok());
+        let invalid = [
+            TraitDistribution::Constant(-1.0),
+            TraitDistribution::Uniform { min: 3.0, max: 1.0 },
+            TraitDistribution::Normal {
+                mean: 10.0,
+                std_dev: 1.0,
+                min: f32::NAN,
+                max: 12.0,
+            },
+            TraitDistribution::Normal {
+                mean: 10.0,
+                std_dev: -1.0,
+                min: 8.0,
+                max: 12.0,
+            },
+        ];
+        for preferred_speed in invalid {
+            let config = WalkerConfig {
+                preferred_speed,
+                ..default()
+            };
+            assert!(config.validate().is_err(), "{:?}", preferred_speed);
+        }
+    }
+
+    #[test]
+    fn speed_falls_from_free_at_no_density_to_zero_at_jam_density() {
+        let curves = [
+            SpeedDensityCurve::Weidmann {
+                gamma: 0.354,
+                jam_density: 1.0,
+            },
+            SpeedDensityCurve::Linear { jam_density: 1.0 },
+        ];
+        for curve in curves {
+            assert_eq!(curve.speed_factor(0.0), 1.0);
+            assert_eq!(curve.speed_factor(1.0), 0.0);
+            assert_eq!(curve.speed_factor(2.0), 0.0);
+            let factors: Vec<_> = (1..=10)
+                .map(|step| curve.speed_factor(step as f32 * 0.1))
+                .collect();
+            assert!(
+                factors.windows(2).all(|pair| pair[1] <= pair[0]),
+                "{curve:?}"
+            );
+            assert!(factors.iter().all(|factor| (0.0..=1.0).contains(factor)));
+        }
+    }
+
+    #[test]
+    fn density_slowdown_is_floored_at_min_speed_factor() {
+        let config = DensityConfig::default();
+        assert_eq!(config.speed_factor(0.0), 1.0);
+        assert_eq!(config.speed_factor(1.0), config.min_speed_factor);
+        assert_eq!(config.speed_factor(5.0), config.min_speed_factor);
+        assert!(config.speed_factor(0.5) > config.min_speed_factor);
+
+        let disabled = DensityConfig {
+            enabled: false,
+            ..default()
+        };
+        assert_eq!(disabled.speed_factor(5.0), 1.0);
+    }
+}
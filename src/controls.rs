@@ -1,18 +1,16 @@
-use crate::{camera::GameCamera, person::*, player::Player};
+use crate::{
+    camera::GameCamera,
+    person::*,
+    player::{Player, PlayerConfig},
+};
 use bevy::prelude::*;
 
-const UP_KEY: KeyCode = KeyCode::Comma;
-const DOWN_KEY: KeyCode = KeyCode::O;
-const LEFT_KEY: KeyCode = KeyCode::A;
-const RIGHT_KEY: KeyCode = KeyCode::E;
-const ZOOM_OUT: KeyCode = KeyCode::K;
-const ZOOM_IN: KeyCode = KeyCode::J;
-
 pub fn player_movement(
     keyboard: Res<Input<KeyCode>>,
+    config: Res<PlayerConfig>,
     mut player: Query<&mut Person, With<Player>>,
 ) {
-    let dir = get_direction(&keyboard);
+    let dir = get_direction(&keyboard, &config);
     let mut player = player.single_mut();
     if dir.length() > 0.1 {
         player.state = PersonState::Walking(dir);
@@ -23,29 +21,30 @@ pub fn player_movement(
 
 pub fn camera_zoom(
     keyboard: Res<Input<KeyCode>>,
+    config: Res<PlayerConfig>,
     mut camera: Query<&mut OrthographicProjection, With<GameCamera>>,
 ) {
     let mut projection = camera.single_mut();
-    if keyboard.just_pressed(ZOOM_OUT) {
+    if keyboard.just_pressed(config.zoom_out) {
         projection.scale = 2.0_f32.min(projection.scale + 0.2);
     }
-    if keyboard.just_pressed(ZOOM_IN) {
+    if keyboard.just_pressed(config.zoom_in) {
         projection.scale = 0.2_f32.max(projection.scale - 0.2);
     }
 }
 
-fn get_direction(keyboard: &Input<KeyCode>) -> Vec2 {
+fn get_direction(keyboard: &Input<KeyCode>, config: &PlayerConfig) -> Vec2 {
     let mut dir = Vec2::ZERO;
-    if keyboard.pressed(UP_KEY) {
+    if keyboard.pressed(config.up) {
         dir.y += 1.0;
     }
-    if keyboard.pressed(DOWN_KEY) {
+    if keyboard.pressed(config.down) {
         dir.y -= 1.0;
     }
-    if keyboard.pressed(RIGHT_KEY) {
+    if keyboard.pressed(config.right) {
         dir.x += 1.0;
     }
-    if keyboard.pressed(LEFT_KEY) {
+    if keyboard.pressed(config.left) {
         dir.x -= 1.0;
     }
     dir.normalize_or_zero()
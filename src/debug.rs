@@ -0,0 +1,47 @@
+use crate::{
+    ai::{path_debug, Target},
+    config::CrowdConfig,
+    person::add_person,
+};
+use bevy::prelude::*;
+
+/// Debug visualisations, all disabled by default.
+pub struct DebugPlugin;
+
+#[derive(Default)]
+pub struct DebugConfig {
+    /// Draws the remaining path of every person.
+    pub path_debug: bool,
+}
+
+impl Plugin for DebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugConfig>()
+            .add_system(path_debug::path_debug);
+    }
+}
+
+pub fn collision_scenario(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    config: &CrowdConfig,
+) {
+    let x = 77.0;
+    let x_offset = -0.1;
+    let person_entity = add_person(
+        commands,
+        meshes,
+        materials,
+        config,
+        Vec2::new(x + x_offset, 60.0),
+    );
+    commands
+        .entity(person_entity)
+        .insert(Target(Vec2::new(x + x_offset, 20.0)));
+
+    let person_entity = add_person(commands, meshes, materials, config, Vec2::new(x, 20.0));
+    commands
+        .entity(person_entity)
+        .insert(Target(Vec2::new(x, 60.0)));
+}
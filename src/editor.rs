@@ -0,0 +1,253 @@
+use crate::{
+    building::*,
+    camera::{self, GameCamera},
+    level::*,
+    road::*,
+    transit::TransitLine,
+};
+use bevy::prelude::*;
+
+const TOGGLE_EDITOR: KeyCode = KeyCode::Tab;
+const NODE_TOOL: KeyCode = KeyCode::Key1;
+const ROAD_TOOL: KeyCode = KeyCode::Key2;
+const BUILDING_TOOL: KeyCode = KeyCode::Key3;
+const DOOR_TOOL: KeyCode = KeyCode::Key4;
+const SAVE_LEVEL: KeyCode = KeyCode::F2;
+
+const NODE_PICK_RADIUS: f32 = 10.0;
+const DOOR_PICK_DISTANCE: f32 = 3.0;
+const MIN_BUILDING_SIZE: f32 = 5.0;
+
+/// Editor for roads and buildings, toggled with Tab.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorState>()
+            .add_system(editor_controls)
+            .add_system(edit_level)
+            .add_system(save_level);
+    }
+}
+
+#[derive(Default)]
+pub struct EditorState {
+    pub enabled: bool,
+    pub tool: EditorTool,
+    dragged_node: Option<Entity>,
+    road_start: Option<Entity>,
+    building_start: Option<Vec2>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorTool {
+    #[default]
+    Node,
+    Road,
+    Building,
+    Door,
+}
+
+pub fn editor_controls(keyboard: Res<Input<KeyCode>>, mut editor: ResMut<EditorState>) {
+    if keyboard.just_pressed(TOGGLE_EDITOR) {
+        editor.enabled = !editor.enabled;
+        info!(
+            "Editor {}",
+            if editor.enabled {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+    }
+    if !editor.enabled {
+        return;
+    }
+
+    for (key, tool) in [
+        (NODE_TOOL, EditorTool::Node),
+        (ROAD_TOOL, EditorTool::Road),
+        (BUILDING_TOOL, EditorTool::Building),
+        (DOOR_TOOL, EditorTool::Door),
+    ] {
+        if keyboard.just_pressed(key) && editor.tool != tool {
+            *editor = EditorState {
+                enabled: true,
+                tool,
+                ..default()
+            };
+            info!("Editor tool: {:?}", tool);
+        }
+    }
+}
+
+pub fn edit_level(
+    mut commands: Commands,
+    mut editor: ResMut<EditorState>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    camera: Query<(&Camera, &GlobalTransform), With<GameCamera>>,
+    mut nodes: Query<(Entity, &mut RoadNode)>,
+    mut buildings: Query<(Entity, &mut Building)>,
+) {
+    if !editor.enabled {
+        return;
+    }
+    let (camera, camera_transform) = camera.single();
+    let cursor_pos = match camera::cursor_world_pos(&windows, camera, camera_transform) {
+        Some(cursor_pos) => cursor_pos,
+        None => return,
+    };
+
+    let tool = editor.tool;
+    match tool {
+        EditorTool::Node => edit_nodes(&mut commands, &mut editor, &mouse, cursor_pos, &mut nodes),
+        EditorTool::Road => edit_roads(&mut commands, &mut editor, &mouse, cursor_pos, &nodes),
+        EditorTool::Building => {
+            edit_buildings(&mut commands, &mut editor, &mouse, cursor_pos, &buildings)
+        }
+        EditorTool::Door => edit_doors(&mouse, cursor_pos, &mut buildings),
+    }
+}
+
+pub fn save_level(
+    keyboard: Res<Input<KeyCode>>,
+    editor: Res<EditorState>,
+    nodes: Query<(Entity, &RoadNode)>,
+    roads: Query<&Road>,
+    buildings: Query<&Building>,
+    lines: Query<&TransitLine>,
+) {
+    if !editor.enabled || !keyboard.just_pressed(SAVE_LEVEL) {
+        return;
+    }
+    let level = Level::from_world(nodes.iter(), roads.iter(), buildings.iter(), lines.iter());
+    match level.save(LEVEL_PATH) {
+        Ok(()) => info!("Saved level to {}", LEVEL_PATH),
+        Err(err) => error!("Could not save level to {}: {}", LEVEL_PATH, err),
+    }
+}
+
+fn edit_nodes(
+    commands: &mut Commands,
+    editor: &mut EditorState,
+    mouse: &Input<MouseButton>,
+    cursor_pos: Vec2,
+    nodes: &mut Query<(Entity, &mut RoadNode)>,
+) {
+    if mouse.just_pressed(MouseButton::Left) {
+        let node_entity = node_at(nodes, cursor_pos)
+            .unwrap_or_else(|| commands.spawn().insert(RoadNode { pos: cursor_pos }).id());
+        editor.dragged_node = Some(node_entity);
+    }
+    if mouse.just_pressed(MouseButton::Right) {
+        if let Some(node_entity) = node_at(nodes, cursor_pos) {
+            commands.entity(node_entity).despawn_recursive();
+        }
+    }
+    if mouse.pressed(MouseButton::Left) {
+        if let Some(Ok((_, mut node))) = editor.dragged_node.map(|entity| nodes.get_mut(entity)) {
+            if node.pos != cursor_pos {
+                node.pos = cursor_pos;
+            }
+        }
+    }
+    if mouse.just_released(MouseButton::Left) {
+        editor.dragged_node = None;
+    }
+}
+
+fn edit_roads(
+    commands: &mut Commands,
+    editor: &mut EditorState,
+    mouse: &Input<MouseButton>,
+    cursor_pos: Vec2,
+    nodes: &Query<(Entity, &mut RoadNode)>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+    if let Some(node_entity) = node_at(nodes, cursor_pos) {
+        match editor.road_start.take() {
+            Some(start_entity) if start_entity != node_entity => {
+                commands
+                    .spawn()
+                    .insert(Road::new(start_entity, node_entity));
+            }
+            _ => editor.road_start = Some(node_entity),
+        }
+    } else {
+        editor.road_start = None;
+    }
+}
+
+fn edit_buildings(
+    commands: &mut Commands,
+    editor: &mut EditorState,
+    mouse: &Input<MouseButton>,
+    cursor_pos: Vec2,
+    buildings: &Query<(Entity, &mut Building)>,
+) {
+    if mouse.just_pressed(MouseButton::Left) {
+        editor.building_start = Some(cursor_pos);
+    }
+    if mouse.just_pressed(MouseButton::Right) {
+        if let Some((building_entity, _)) = buildings
+            .iter()
+            .find(|(_, building)| building.contains(cursor_pos))
+        {
+            commands.entity(building_entity).despawn_recursive();
+        }
+    }
+    if mouse.just_released(MouseButton::Left) {
+        if let Some(start_pos) = editor.building_start.take() {
+            let size = (cursor_pos - start_pos).abs();
+            if size.min_element() >= MIN_BUILDING_SIZE {
+                commands.spawn().insert(Building {
+                    pos: (start_pos + cursor_pos) / 2.0,
+                    size,
+                    doors: vec![],
+                });
+            }
+        }
+    }
+}
+
+fn edit_doors(
+    mouse: &Input<MouseButton>,
+    cursor_pos: Vec2,
+    buildings: &mut Query<(Entity, &mut Building)>,
+) {
+    if mouse.just_pressed(MouseButton::Left) {
+        let closest = buildings
+            .iter()
+            .map(|(entity, building)| (entity, building.closest_side(cursor_pos)))
+            .filter(|(_, (_, _, distance))| *distance < DOOR_PICK_DISTANCE)
+            .min_by(|(_, (_, _, a)), (_, (_, _, b))| a.total_cmp(b));
+        if let Some((building_entity, (side, pos, _))) = closest {
+            let (_, mut building) = buildings.get_mut(building_entity).unwrap();
+            building.doors.push(Door::new(side, pos));
+        }
+    }
+    if mouse.just_pressed(MouseButton::Right) {
+        for (_, mut building) in buildings.iter_mut() {
+            let door_index = building
+                .doors
+                .iter()
+                .position(|door| building.door_pos(door).distance(cursor_pos) < DOOR_PICK_DISTANCE);
+            if let Some(door_index) = door_index {
+                building.doors.remove(door_index);
+                break;
+            }
+        }
+    }
+}
+
+fn node_at(nodes: &Query<(Entity, &mut RoadNode)>, pos: Vec2) -> Option<Entity> {
+    nodes
+        .iter()
+        .map(|(entity, node)| (entity, node.pos.distance(pos)))
+        .filter(|(_, distance)| *distance < NODE_PICK_RADIUS)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity)
+}
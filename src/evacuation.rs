@@ -0,0 +1,474 @@
+//! Evacuation scenario: once started, spawning stops, everyone outside heads to the safe exit zone
+//! that is the best trade-off between distance and crowding, and the occupants of every building
+//! leave through its doors. People evacuating panic, walking faster with less personal space.
+
+use crate::{
+    ai::{
+        group::Group,
+        stuck::{AvoidCrowd, GaveUp},
+        BuildPath, CrowdAiLabel, Target, TargetDoor, TripFailed, WaitSpot,
+    },
+    building::{Building, Door},
+    config::{CrowdConfig, DoorConfig, EvacuationConfig, SpawnConfig, WalkerConfig},
+    person::{self, GroupMember, Person, PersonLabel, Walker},
+    player::Player,
+    rng::SimRng,
+    simulation::SimClock,
+};
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+use bevy_prototype_lyon::prelude::{FillMode, *};
+
+const START_EVACUATION: KeyCode = KeyCode::F6;
+
+pub struct EvacuationPlugin;
+
+#[derive(SystemLabel)]
+pub enum EvacuationLabel {
+    Trigger,
+}
+
+impl Plugin for EvacuationPlugin {
+    fn build(&self, app: &mut App) {
+        crate::add_event_once::<GaveUp>(app);
+        crate::add_event_once::<TripFailed>(app);
+        app.init_resource::<EvacuationConfig>()
+            .init_resource::<DoorConfig>()
+            .init_resource::<SpawnConfig>()
+            .init_resource::<CrowdConfig>()
+            .init_resource::<WalkerConfig>()
+            .init_resource::<EvacuationReport>()
+            .add_startup_system(draw_exit_zones)
+            .add_system(
+                trigger_evacuation
+                    .label(EvacuationLabel::Trigger)
+                    .before(CrowdAiLabel::PathUpdate),
+            )
+            .add_system(release_occupants.after(EvacuationLabel::Trigger))
+            .add_system(reach_exit_zones.after(PersonLabel::Movement))
+            .add_system(record_lost_evacuees.after(CrowdAiLabel::TrackProgress));
+    }
+}
+
+/// Marks a person taking part in the evacuation, with the building and door it left through if it
+/// was inside.
+#[derive(Component, Debug)]
+pub struct Evacuee {
+    pub building: Option<Entity>,
+    pub door: Option<Entity>,
+}
+
+/// Occupants of a building still to go out through this door.
+#[derive(Component, Debug)]
+pub struct Occupants {
+    remaining: u32,
+    /// Simulation time at which the next occupant may go out.
+    next_exit: f32,
+}
+
+/// Evacuation time per building and clearing time per door, since the evacuation started.
+#[derive(Default, Debug)]
+pub struct EvacuationReport {
+    pub started_at: Option<f32>,
+    pub completed_at: Option<f32>,
+    pub buildings: HashMap<Entity, EvacuationCount>,
+    /// People that were outside when the evacuation started.
+    pub outside: EvacuationCount,
+    pub doors: HashMap<Entity, DoorEvacuation>,
+    /// People sent to each exit zone, in the order of the config.
+    pub exits: Vec<ExitLoad>,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct EvacuationCount {
+    pub pos: Vec2,
+    pub people: u32,
+    pub evacuated: u32,
+    /// People that gave up on the way or found no path to an exit.
+    pub lost: u32,
+    /// Simulation time at which the last person reached an exit zone.
+    pub last_evacuated_at: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExitLoad {
+    pub pos: Vec2,
+    pub people: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DoorEvacuation {
+    pub pos: Vec2,
+    pub people: u32,
+    /// Simulation time at which the last occupant went out.
+    pub cleared_at: Option<f32>,
+}
+
+impl EvacuationCount {
+    fn is_complete(&self) -> bool {
+        self.evacuated + self.lost >= self.people
+    }
+}
+
+impl EvacuationReport {
+    pub fn is_complete(&self) -> bool {
+        self.outside.is_complete()
+            && self
+                .buildings
+                .values()
+                .all(|building| building.is_complete())
+    }
+
+    fn count_mut(&mut self, evacuee: &Evacuee) -> Option<&mut EvacuationCount> {
+        match evacuee.building {
+            Some(building) => self.buildings.get_mut(&building),
+            None => Some(&mut self.outside),
+        }
+    }
+
+    fn check_complete(&mut self, now: f32) {
+        if let Some(started_at) = self.started_at {
+            if self.completed_at.is_none() && self.is_complete() {
+                info!("Evacuation complete after {:.1} s", now - started_at);
+                self.completed_at = Some(now);
+            }
+        }
+    }
+
+    /// Returns the position of the exit zone with the lowest distance from `pos`, counting the
+    /// people already sent to an exit as extra distance, and sends `people` more there.
+    fn assign_exit(&mut self, congestion_weight: f32, pos: Vec2, people: u32) -> Vec2 {
+        let cost =
+            |exit: &ExitLoad| exit.pos.distance(pos) + congestion_weight * exit.people as f32;
+        let exit = self
+            .exits
+            .iter_mut()
+            .min_by(|a, b| cost(a).total_cmp(&cost(b)))
+            .unwrap();
+        exit.people += people;
+        exit.pos
+    }
+
+    pub fn print_report(&self) {
+        let started_at = match self.started_at {
+            Some(started_at) => started_at,
+            None => return,
+        };
+        match self.completed_at {
+            Some(completed_at) => println!(
+                "Evacuation started at {:.1} s, complete after {:.1} s",
+                started_at,
+                completed_at - started_at
+            ),
+            None => println!("Evacuation started at {:.1} s, not complete", started_at),
+        }
+
+        let print_count = |name: String, count: &EvacuationCount| {
+            let lost = if count.lost > 0 {
+                format!(", {} lost", count.lost)
+            } else {
+                String::new()
+            };
+            match count.last_evacuated_at {
+                Some(last) if count.is_complete() => println!(
+                    "{}: {} people out in {:.1} s{}",
+                    name,
+                    count.evacuated,
+                    last - started_at,
+                    lost
+                ),
+                _ => println!(
+                    "{}: {}/{} people out{}",
+                    name, count.evacuated, count.people, lost
+                ),
+            }
+        };
+        print_count("Outside".to_string(), &self.outside);
+        let mut buildings: Vec<_> = self.buildings.values().collect();
+        buildings.sort_by(|a, b| {
+            a.pos
+                .x
+                .total_cmp(&b.pos.x)
+                .then(a.pos.y.total_cmp(&b.pos.y))
+        });
+        for building in buildings {
+            print_count(
+                format!("Building at ({:.0}, {:.0})", building.pos.x, building.pos.y),
+                building,
+            );
+        }
+
+        // The doors that took the longest to clear are the bottlenecks.
+        let mut doors: Vec<_> = self.doors.values().collect();
+        doors.sort_by(|a, b| {
+            let a_time = a.cleared_at.unwrap_or(f32::INFINITY);
+            let b_time = b.cleared_at.unwrap_or(f32::INFINITY);
+            b_time.total_cmp(&a_time)
+        });
+        for door in doors {
+            match door.cleared_at {
+                Some(cleared_at) => println!(
+                    "Door at ({:.0}, {:.0}): {} people, cleared after {:.1} s",
+                    door.pos.x,
+                    door.pos.y,
+                    door.people,
+                    cleared_at - started_at
+                ),
+                None => println!(
+                    "Door at ({:.0}, {:.0}): {} people, not cleared",
+                    door.pos.x, door.pos.y, door.people
+                ),
+            }
+        }
+
+        for exit in &self.exits {
+            println!(
+                "Exit at ({:.0}, {:.0}): {} people",
+                exit.pos.x, exit.pos.y, exit.people
+            );
+        }
+    }
+}
+
+fn draw_exit_zones(mut commands: Commands, config: Res<EvacuationConfig>) {
+    for exit in &config.exits {
+        let circle = shapes::Circle {
+            radius: exit.radius,
+            center: Vec2::ZERO,
+        };
+        commands.spawn_bundle(GeometryBuilder::build_as(
+            &circle,
+            DrawMode::Fill(FillMode::color(Color::rgba(0.2, 0.8, 0.2, 0.3))),
+            Transform::from_xyz(exit.pos.x, exit.pos.y, 1.0),
+        ));
+    }
+}
+
+type PlannerQuery<'a> = (Entity, &'a Transform, Option<&'a Group>);
+
+type EvacueeQuery<'a> = (Entity, Option<&'a mut Walker>);
+
+/// Starts the evacuation with F6 or after `start_after` seconds: everyone with a plan heads to an
+/// exit zone instead, and the doors start letting the occupants out.
+#[allow(clippy::too_many_arguments)]
+pub fn trigger_evacuation(
+    mut commands: Commands,
+    config: Res<EvacuationConfig>,
+    clock: Res<SimClock>,
+    keyboard: Option<Res<Input<KeyCode>>>,
+    mut spawn_config: ResMut<SpawnConfig>,
+    mut report: ResMut<EvacuationReport>,
+    planners: Query<PlannerQuery, (With<Target>, Without<GroupMember>)>,
+    mut people: Query<EvacueeQuery, (With<Person>, Without<Player>)>,
+    buildings: Query<&Building>,
+    doors: Query<(Entity, &GlobalTransform, &Parent), With<Door>>,
+) {
+    if report.started_at.is_some() {
+        return;
+    }
+    let now = clock.elapsed_secs();
+    let started = keyboard.is_some_and(|keyboard| keyboard.just_pressed(START_EVACUATION))
+        || config
+            .start_after
+            .is_some_and(|start_after| now >= start_after);
+    if !started {
+        return;
+    }
+    if config.exits.is_empty() {
+        warn!("Can not evacuate without exit zones");
+        return;
+    }
+    info!("Evacuation started at {:.1} s", now);
+    spawn_config.enabled = false;
+    report.started_at = Some(now);
+    report.exits = config
+        .exits
+        .iter()
+        .map(|exit| ExitLoad {
+            pos: exit.pos,
+            people: 0,
+        })
+        .collect();
+
+    for (entity, walker) in people.iter_mut() {
+        match walker {
+            Some(mut walker) => *walker = config.panic.apply(*walker),
+            None => {
+                commands
+                    .entity(entity)
+                    .insert(config.panic.apply(Walker::default()));
+            }
+        }
+        commands
+            .entity(entity)
+            .insert(Evacuee {
+                building: None,
+                door: None,
+            })
+            .remove::<WaitSpot>();
+        report.outside.people += 1;
+    }
+
+    for (entity, transform, group) in planners.iter() {
+        let size = group.map_or(1, |group| group.members.len() as u32);
+        let exit_pos =
+            report.assign_exit(config.congestion_weight, transform.translation.xy(), size);
+        commands
+            .entity(entity)
+            .insert(Target(exit_pos))
+            .remove::<TargetDoor>()
+            .insert(BuildPath)
+            .insert(AvoidCrowd);
+    }
+
+    let mut building_doors: HashMap<Entity, Vec<(Entity, Vec2)>> = HashMap::default();
+    for (door_entity, door_transform, parent) in doors.iter() {
+        building_doors
+            .entry(parent.get())
+            .or_default()
+            .push((door_entity, door_transform.translation().xy()));
+    }
+    for (building_entity, doors) in building_doors {
+        let building = match buildings.get(building_entity) {
+            Ok(building) => building,
+            Err(_) => continue,
+        };
+        report.buildings.insert(
+            building_entity,
+            EvacuationCount {
+                pos: building.pos,
+                people: config.occupants_per_building,
+                ..default()
+            },
+        );
+        // The occupants are split evenly between the doors.
+        let door_count = doors.len() as u32;
+        for (index, (door_entity, door_pos)) in doors.into_iter().enumerate() {
+            let share = config.occupants_per_building / door_count
+                + u32::from((index as u32) < config.occupants_per_building % door_count);
+            report.doors.insert(
+                door_entity,
+                DoorEvacuation {
+                    pos: door_pos,
+                    people: share,
+                    cleared_at: None,
+                },
+            );
+            if share == 0 {
+                continue;
+            }
+            commands.entity(door_entity).insert(Occupants {
+                remaining: share,
+                next_exit: now,
+            });
+        }
+    }
+    for building in buildings.iter() {
+        if building.doors.is_empty() {
+            warn!(
+                "The occupants of the building at {} have no door to get out",
+                building.pos
+            );
+        }
+    }
+}
+
+/// Lets the occupants of the buildings out through their doors, no faster than the door capacity.
+#[allow(clippy::too_many_arguments)]
+pub fn release_occupants(
+    mut commands: Commands,
+    config: Res<EvacuationConfig>,
+    door_config: Res<DoorConfig>,
+    spawn_config: Res<SpawnConfig>,
+    crowd_config: Res<CrowdConfig>,
+    walker_config: Res<WalkerConfig>,
+    clock: Res<SimClock>,
+    mut rng: ResMut<SimRng>,
+    mut report: ResMut<EvacuationReport>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut doors: Query<(Entity, &GlobalTransform, &Door, &Parent, &mut Occupants)>,
+) {
+    let now = clock.elapsed_secs();
+    for (door_entity, door_transform, door, parent, mut occupants) in doors.iter_mut() {
+        if now < occupants.next_exit {
+            continue;
+        }
+        let pos =
+            door_transform.translation().xy() + spawn_config.door_distance * door.get_open_dir();
+        let walker = config.panic.apply(walker_config.sample(&mut **rng));
+        let exit_pos = report.assign_exit(config.congestion_weight, pos, 1);
+        let person_entity = person::add_person(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &crowd_config,
+            pos,
+        );
+        commands
+            .entity(person_entity)
+            .insert(walker)
+            .insert(Evacuee {
+                building: Some(parent.get()),
+                door: Some(door_entity),
+            })
+            .insert(Target(exit_pos))
+            .insert(BuildPath)
+            .insert(AvoidCrowd);
+
+        occupants.remaining -= 1;
+        occupants.next_exit = now + 1.0 / door_config.capacity;
+        if occupants.remaining == 0 {
+            commands.entity(door_entity).remove::<Occupants>();
+            if let Some(door_evacuation) = report.doors.get_mut(&door_entity) {
+                door_evacuation.cleared_at = Some(now);
+            }
+        }
+    }
+}
+
+/// Takes the people reaching an exit zone out of the simulation and records their evacuation.
+pub fn reach_exit_zones(
+    mut commands: Commands,
+    config: Res<EvacuationConfig>,
+    clock: Res<SimClock>,
+    mut report: ResMut<EvacuationReport>,
+    evacuees: Query<(Entity, &Transform, &Evacuee)>,
+) {
+    let now = clock.elapsed_secs();
+    for (entity, transform, evacuee) in evacuees.iter() {
+        let pos = transform.translation.xy();
+        if !config.exits.iter().any(|exit| exit.contains(pos)) {
+            continue;
+        }
+        if let Some(count) = report.count_mut(evacuee) {
+            count.evacuated += 1;
+            count.last_evacuated_at = Some(now);
+        }
+        commands.entity(entity).despawn();
+    }
+    report.check_complete(now);
+}
+
+/// Counts the evacuees that gave up or found no path, so the evacuation can still complete.
+pub fn record_lost_evacuees(
+    clock: Res<SimClock>,
+    mut report: ResMut<EvacuationReport>,
+    mut gave_up_events: EventReader<GaveUp>,
+    mut failure_events: EventReader<TripFailed>,
+    evacuees: Query<&Evacuee>,
+) {
+    let lost = gave_up_events
+        .iter()
+        .map(|gave_up| gave_up.0)
+        .chain(failure_events.iter().map(|failure| failure.0));
+    for entity in lost {
+        if let Some(count) = evacuees
+            .get(entity)
+            .ok()
+            .and_then(|evacuee| report.count_mut(evacuee))
+        {
+            count.lost += 1;
+        }
+    }
+    report.check_complete(clock.elapsed_secs());
+}
@@ -0,0 +1,54 @@
+use crate::{evacuation::EvacuationReport, metrics::SimMetrics, simulation::SimClock};
+use bevy::{
+    app::{AppExit, ScheduleRunnerSettings},
+    asset::AssetPlugin,
+    hierarchy::HierarchyPlugin,
+    prelude::*,
+    transform::TransformPlugin,
+    utils::Duration,
+};
+
+const DEFAULT_DURATION: f32 = 60.0;
+
+/// Simulated seconds to run for, when started with `--headless [seconds]`.
+#[derive(Deref)]
+pub struct HeadlessDuration(pub f32);
+
+pub fn duration_from_args() -> Option<HeadlessDuration> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--headless");
+    args.next()?;
+    let duration = args
+        .next()
+        .and_then(|duration| duration.parse().ok())
+        .unwrap_or(DEFAULT_DURATION);
+    Some(HeadlessDuration(duration))
+}
+
+/// Sets up the app without a window or rendering, stepping the simulation as fast as possible.
+pub fn add_headless_plugins(app: &mut App, duration: HeadlessDuration) {
+    app.insert_resource(ScheduleRunnerSettings::run_loop(Duration::ZERO))
+        .add_plugins(MinimalPlugins)
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(AssetPlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<ColorMaterial>()
+        .insert_resource(duration)
+        .add_system_to_stage(CoreStage::Last, stop_after_duration);
+}
+
+fn stop_after_duration(
+    duration: Res<HeadlessDuration>,
+    clock: Res<SimClock>,
+    metrics: Res<SimMetrics>,
+    evacuation: Option<Res<EvacuationReport>>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    if clock.elapsed_secs() >= **duration {
+        metrics.print_report(&clock);
+        if let Some(evacuation) = evacuation {
+            evacuation.print_report();
+        }
+        app_exit.send(AppExit);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{building::Building, road::*, transit::TransitLine};
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, fs, path::Path};
+
+pub const LEVEL_PATH: &str = "assets/level.ron";
+
+/// Serializable description of a map. Roads and transit lines reference nodes by their index in
+/// `nodes`.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Level {
+    pub nodes: Vec<RoadNode>,
+    pub roads: Vec<LevelRoad>,
+    pub buildings: Vec<Building>,
+    #[serde(default)]
+    pub lines: Vec<LevelTransitLine>,
+}
+
+/// A road between two nodes. Levels without the other fields get two way streets of the default
+/// size.
+#[derive(Serialize, Deserialize)]
+pub struct LevelRoad {
+    pub from: usize,
+    pub to: usize,
+    #[serde(default)]
+    pub kind: RoadKind,
+    #[serde(default = "default_lanes")]
+    pub lanes: u32,
+    #[serde(default = "default_width")]
+    pub width: f32,
+    #[serde(default)]
+    pub one_way: bool,
+    #[serde(default = "default_speed_limit")]
+    pub speed_limit: f32,
+    #[serde(default = "default_sidewalk_width")]
+    pub sidewalk_width: f32,
+    #[serde(default)]
+    pub curve: RoadCurve,
+}
+
+/// A bus line through the nodes at these indices, see `TransitLine`.
+#[derive(Serialize, Deserialize)]
+pub struct LevelTransitLine {
+    pub nodes: Vec<usize>,
+    pub stops: Vec<Vec2>,
+    pub headway: f32,
+}
+
+fn default_lanes() -> u32 {
+    DEFAULT_LANES
+}
+
+fn default_width() -> f32 {
+    ROAD_WIDTH
+}
+
+fn default_speed_limit() -> f32 {
+    DEFAULT_SPEED_LIMIT
+}
+
+fn default_sidewalk_width() -> f32 {
+    DEFAULT_SIDEWALK_WIDTH
+}
+
+impl Level {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let level: Self = read_ron(path)?;
+        level.validate()?;
+        Ok(level)
+    }
+
+    /// Checks that every node index refers to one of the nodes, as a hand edited file may not.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (index, road) in self.roads.iter().enumerate() {
+            for node in [road.from, road.to] {
+                if node >= self.nodes.len() {
+                    return Err(format!(
+                        "road {} refers to node {}, but there are {} nodes",
+                        index,
+                        node,
+                        self.nodes.len()
+                    )
+                    .into());
+                }
+            }
+        }
+        for (index, line) in self.lines.iter().enumerate() {
+            if let Some(&node) = line.nodes.iter().find(|&&node| node >= self.nodes.len()) {
+                return Err(format!(
+                    "transit line {} refers to node {}, but there are {} nodes",
+                    index,
+                    node,
+                    self.nodes.len()
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        write_ron(self, path)
+    }
+
+    pub fn from_world<'a>(
+        nodes: impl Iterator<Item = (Entity, &'a RoadNode)>,
+        roads: impl Iterator<Item = &'a Road>,
+        buildings: impl Iterator<Item = &'a Building>,
+        lines: impl Iterator<Item = &'a TransitLine>,
+    ) -> Self {
+        let mut level = Level::default();
+        let mut node_indices = HashMap::new();
+        for (entity, node) in nodes {
+            node_indices.insert(entity, level.nodes.len());
+            level.nodes.push(node.clone());
+        }
+        level.roads = roads
+            .filter_map(|road| {
+                Some(LevelRoad {
+                    from: *node_indices.get(&road.from)?,
+                    to: *node_indices.get(&road.to)?,
+                    kind: road.kind,
+                    lanes: road.lanes,
+                    width: road.width,
+                    one_way: road.one_way,
+                    speed_limit: road.speed_limit,
+                    sidewalk_width: road.sidewalk_width,
+                    curve: road.curve,
+                })
+            })
+            .collect();
+        level.buildings = buildings.cloned().collect();
+        level.lines = lines
+            .filter_map(|line| {
+                Some(LevelTransitLine {
+                    nodes: line
+                        .nodes
+                        .iter()
+                        .map(|node| node_indices.get(node).copied())
+                        .collect::<Option<_>>()?,
+                    stops: line.stops.clone(),
+                    headway: line.headway,
+                })
+            })
+            .collect();
+        level
+    }
+
+    /// Spawns the level, returning the entities of its nodes and of its transit lines.
+    pub fn spawn(&self, commands: &mut Commands) -> (Vec<Entity>, Vec<Entity>) {
+        let node_entities: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| commands.spawn().insert(node.clone()).id())
+            .collect();
+        for road in &self.roads {
+            commands.spawn().insert(Road {
+                from: node_entities[road.from],
+                to: node_entities[road.to],
+                kind: road.kind,
+                lanes: road.lanes,
+                width: road.width,
+                one_way: road.one_way,
+                speed_limit: road.speed_limit,
+                sidewalk_width: road.sidewalk_width,
+                curve: road.curve,
+            });
+        }
+        for building in &self.buildings {
+            commands.spawn().insert(building.clone());
+        }
+        let line_entities = self
+            .lines
+            .iter()
+            .map(|line| {
+                commands
+                    .spawn()
+                    .insert(TransitLine {
+                        nodes: line.nodes.iter().map(|&node| node_entities[node]).collect(),
+                        stops: line.stops.clone(),
+                        headway: line.headway,
+                    })
+                    .id()
+            })
+            .collect();
+        (node_entities, line_entities)
+    }
+}
+
+pub fn read_ron<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<T, Box<dyn Error>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(ron::from_str(&contents)?)
+}
+
+pub fn write_ron<T: Serialize>(value: &T, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+    let path = path.as_ref();
+    let contents = ron::ser::to_string_pretty(value, PrettyConfig::default())?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Crowd simulation on a city of roads and buildings.
+//!
+//! The simulation is split into plugins that can be embedded in another app. Each plugin inserts
+//! its config resource with default values, unless the app inserted one before adding it.
+
+pub mod ai;
+pub mod building;
+pub mod camera;
+pub mod city;
+pub mod config;
+pub mod controls;
+pub mod debug;
+pub mod editor;
+pub mod evacuation;
+pub mod headless;
+pub mod level;
+pub mod metrics;
+pub mod od_matrix;
+pub mod person;
+pub mod player;
+pub mod rng;
+pub mod road;
+pub mod signal;
+pub mod simulation;
+pub mod snapshot;
+pub mod spatial_hash;
+pub mod spawning;
+pub mod transit;
+pub mod vehicle;
+
+pub use ai::CrowdAiPlugin;
+pub use city::CityPlugin;
+pub use config::*;
+pub use debug::DebugPlugin;
+pub use editor::EditorPlugin;
+pub use evacuation::EvacuationPlugin;
+pub use person::PersonPlugin;
+pub use player::PlayerPlugin;
+pub use signal::SignalPlugin;
+pub use simulation::SimulationPlugin;
+pub use snapshot::SnapshotPlugin;
+pub use spawning::SpawningPlugin;
+pub use transit::TransitPlugin;
+pub use vehicle::VehiclePlugin;
+
+use bevy::prelude::*;
+
+/// Registers an event unless another plugin already did, so plugins sharing an event can be added
+/// in any order.
+pub(crate) fn add_event_once<T: Send + Sync + 'static>(app: &mut App) {
+    if !app.world.contains_resource::<Events<T>>() {
+        app.add_event::<T>();
+    }
+}
@@ -0,0 +1,174 @@
+use crate::{
+    ai::{
+        stuck::{GaveUp, Stuck},
+        Arrived, TripFailed,
+    },
+    config::StuckResolution,
+    person::{density::LocalDensity, Person, PersonState},
+    simulation::SimClock,
+};
+use bevy::{prelude::*, utils::Duration};
+use bevy_rapier2d::prelude::*;
+
+/// Width of the density bins of the fundamental diagram, in people per square unit.
+pub const DENSITY_BIN_WIDTH: f32 = 0.1;
+
+#[derive(Default, Debug)]
+pub struct SimMetrics {
+    pub trips_completed: u32,
+    pub total_travel_time: f32,
+    /// People that gave up their trip as no path led to their target.
+    pub failed_trips: u32,
+    pub collisions: u32,
+    pub paths_built: u32,
+    pub pathfinding_time: Duration,
+    pub stuck_gave_up: u32,
+    pub stuck_teleported: u32,
+    /// People that crossed a road at a crosswalk, and the seconds they waited at the kerb.
+    pub crossings: u32,
+    pub crossing_wait_time: f32,
+    /// People that got on a bus, and the seconds people waited at the stops.
+    pub bus_rides: u32,
+    pub bus_wait_time: f32,
+    /// Speed of walking people sampled every step, binned by local density.
+    pub fundamental_diagram: Vec<DensityBin>,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct DensityBin {
+    pub samples: u32,
+    pub total_speed: f32,
+}
+
+impl SimMetrics {
+    pub fn mean_travel_time(&self) -> f32 {
+        if self.trips_completed == 0 {
+            0.0
+        } else {
+            self.total_travel_time / self.trips_completed as f32
+        }
+    }
+
+    pub fn mean_crossing_delay(&self) -> f32 {
+        if self.crossings == 0 {
+            0.0
+        } else {
+            self.crossing_wait_time / self.crossings as f32
+        }
+    }
+
+    pub fn mean_bus_wait(&self) -> f32 {
+        if self.bus_rides == 0 {
+            0.0
+        } else {
+            self.bus_wait_time / self.bus_rides as f32
+        }
+    }
+
+    pub fn print_report(&self, clock: &SimClock) {
+        println!(
+            "Simulated {:.1} s ({} ticks)",
+            clock.elapsed_secs(),
+            clock.tick
+        );
+        println!("Trips completed: {}", self.trips_completed);
+        println!("Trips failed: {}", self.failed_trips);
+        println!("Mean travel time: {:.2} s", self.mean_travel_time());
+        println!("Collisions: {}", self.collisions);
+        println!(
+            "Pathfinding time: {:.1} ms over {} paths",
+            self.pathfinding_time.as_secs_f64() * 1000.0,
+            self.paths_built
+        );
+        println!(
+            "Stuck: {} gave up, {} teleported",
+            self.stuck_gave_up, self.stuck_teleported
+        );
+        println!(
+            "Crossings: {}, mean delay at the kerb {:.2} s",
+            self.crossings,
+            self.mean_crossing_delay()
+        );
+        println!(
+            "Bus rides: {}, mean wait at the stop {:.2} s",
+            self.bus_rides,
+            self.mean_bus_wait()
+        );
+        println!("Density (1/unit²)  Speed (unit/s)  Flow (1/unit/s)");
+        for (index, bin) in self.fundamental_diagram.iter().enumerate() {
+            if bin.samples == 0 {
+                continue;
+            }
+            let density = (index as f32 + 0.5) * DENSITY_BIN_WIDTH;
+            let speed = bin.total_speed / bin.samples as f32;
+            println!("{:17.2}  {:14.2}  {:15.2}", density, speed, density * speed);
+        }
+    }
+}
+
+/// Simulation time at which a person started its trip.
+#[derive(Component, Deref)]
+pub struct TripStart(pub f32);
+
+pub fn record_trips(
+    mut metrics: ResMut<SimMetrics>,
+    clock: Res<SimClock>,
+    mut arrivals: EventReader<Arrived>,
+    mut failures: EventReader<TripFailed>,
+    trips: Query<&TripStart>,
+) {
+    for arrived in arrivals.iter() {
+        if let Ok(trip_start) = trips.get(arrived.0) {
+            metrics.trips_completed += 1;
+            metrics.total_travel_time += clock.elapsed_secs() - **trip_start;
+        }
+    }
+    metrics.failed_trips += failures.iter().count() as u32;
+}
+
+pub fn record_collisions(
+    mut metrics: ResMut<SimMetrics>,
+    mut collision_events: EventReader<CollisionEvent>,
+    people: Query<(), With<Person>>,
+) {
+    for collision_event in collision_events.iter() {
+        if let CollisionEvent::Started(a, b, _) = collision_event {
+            if people.get(*a).is_ok() && people.get(*b).is_ok() {
+                metrics.collisions += 1;
+            }
+        }
+    }
+}
+
+pub fn record_stuck(
+    mut metrics: ResMut<SimMetrics>,
+    mut stuck_events: EventReader<Stuck>,
+    mut gave_up_events: EventReader<GaveUp>,
+) {
+    for stuck in stuck_events.iter() {
+        if stuck.resolution == StuckResolution::Teleport {
+            metrics.stuck_teleported += 1;
+        }
+    }
+    metrics.stuck_gave_up += gave_up_events.iter().count() as u32;
+}
+
+pub fn record_fundamental_diagram(
+    mut metrics: ResMut<SimMetrics>,
+    people: Query<(&Person, &Velocity, &LocalDensity)>,
+) {
+    for (person, velocity, density) in people.iter() {
+        if !matches!(person.state, PersonState::Walking(_)) {
+            continue;
+        }
+        let index = (**density / DENSITY_BIN_WIDTH) as usize;
+        if metrics.fundamental_diagram.len() <= index {
+            metrics
+                .fundamental_diagram
+                .resize(index + 1, DensityBin::default());
+        }
+        let bin = &mut metrics.fundamental_diagram[index];
+        bin.samples += 1;
+        bin.total_speed += velocity.linvel.length();
+    }
+}
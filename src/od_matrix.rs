@@ -0,0 +1,192 @@
+//! Origin-destination demand: trips per hour from every door or zone to every other, read from a
+//! CSV file, for spawning to sample trips from as Poisson arrivals and so reproduce measured
+//! demand.
+//!
+//! Every line of the file is `origin,destination,trips_per_hour[,from,until]`. The origin and
+//! the destination are either the name of one of the zones of the spawn config or the `x y`
+//! coordinates of a door, standing for the door closest to them. The rate only applies from
+//! `from` until `until`, in simulated seconds, when they are given. Empty lines, lines starting
+//! with `#` and a header line are skipped, while any other malformed line is an error.
+
+use crate::config::OdZone;
+use bevy::prelude::*;
+use std::{error::Error, fs, path::Path};
+
+#[derive(Clone, Debug)]
+pub enum OdEndpoint {
+    /// The door closest to this position.
+    Door(Vec2),
+    /// Any door within the zone, or any point of it when it has none.
+    Zone(OdZone),
+}
+
+#[derive(Clone, Debug)]
+pub struct OdFlow {
+    pub origin: OdEndpoint,
+    pub destination: OdEndpoint,
+    pub trips_per_hour: f32,
+    pub from: Option<f32>,
+    pub until: Option<f32>,
+}
+
+impl OdFlow {
+    /// Trips per second at `time`.
+    pub fn rate(&self, time: f32) -> f32 {
+        let started = self.from.is_none_or(|from| time >= from);
+        let ended = self.until.is_some_and(|until| time >= until);
+        if started && !ended {
+            self.trips_per_hour / 3600.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Demand spawning samples trips from, empty unless the spawn config names a file to load it
+/// from.
+#[derive(Default, Clone, Debug)]
+pub struct OdMatrix {
+    pub flows: Vec<OdFlow>,
+}
+
+impl OdMatrix {
+    pub fn load(path: impl AsRef<Path>, zones: &[OdZone]) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&fs::read_to_string(path)?, zones)
+    }
+
+    pub fn parse(contents: &str, zones: &[OdZone]) -> Result<Self, Box<dyn Error>> {
+        let mut flows = vec![];
+        let mut header = true;
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let is_header = header && fields.len() >= 3 && fields[2].parse::<f32>().is_err();
+            header = false;
+            if is_header {
+                continue;
+            }
+            let flow =
+                parse_flow(&fields, zones).map_err(|err| format!("line {}: {}", index + 1, err))?;
+            flows.push(flow);
+        }
+        Ok(Self { flows })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+}
+
+fn parse_flow(fields: &[&str], zones: &[OdZone]) -> Result<OdFlow, String> {
+    if !(3..=5).contains(&fields.len()) {
+        return Err(format!("expected 3 to 5 fields, found {}", fields.len()));
+    }
+    let time = |index: usize| -> Result<Option<f32>, String> {
+        match fields.get(index) {
+            Some(field) if !field.is_empty() => field
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid time {:?}", field)),
+            _ => Ok(None),
+        }
+    };
+    let trips_per_hour: f32 = fields[2]
+        .parse()
+        .map_err(|_| format!("invalid trips per hour {:?}", fields[2]))?;
+    if trips_per_hour < 0.0 {
+        return Err(format!("negative trips per hour {}", trips_per_hour));
+    }
+    let (from, until) = (time(3)?, time(4)?);
+    if let (Some(from), Some(until)) = (from, until) {
+        if from >= until {
+            return Err(format!(
+                "time window from {} until {} is empty",
+                from, until
+            ));
+        }
+    }
+    Ok(OdFlow {
+        origin: parse_endpoint(fields[0], zones)?,
+        destination: parse_endpoint(fields[1], zones)?,
+        trips_per_hour,
+        from,
+        until,
+    })
+}
+
+fn parse_endpoint(field: &str, zones: &[OdZone]) -> Result<OdEndpoint, String> {
+    if let Some(zone) = zones.iter().find(|zone| zone.name == field) {
+        return Ok(OdEndpoint::Zone(zone.clone()));
+    }
+    let coordinates: Option<Vec<f32>> = field
+        .split_whitespace()
+        .map(|coordinate| coordinate.parse().ok())
+        .collect();
+    match coordinates.as_deref() {
+        Some(&[x, y]) => Ok(OdEndpoint::Door(Vec2::new(x, y))),
+        _ => Err(format!("unknown zone {:?}", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zones() -> Vec<OdZone> {
+        vec![OdZone {
+            name: "station".to_string(),
+            pos: Vec2::new(10.0, 20.0),
+            radius: 5.0,
+        }]
+    }
+
+    #[test]
+    fn parses_zones_doors_and_time_windows() {
+        let contents = "origin,destination,trips_per_hour,from,until\n\
+            # Morning peak\n\
+            \n\
+            station, -60 40 ,600\n\
+            -60 40,station,1200,0,300\n\
+            station,station,60,,100\n";
+        let matrix = OdMatrix::parse(contents, &zones()).unwrap();
+        assert_eq!(matrix.flows.len(), 3);
+
+        let flow = &matrix.flows[0];
+        assert!(matches!(&flow.origin, OdEndpoint::Zone(zone) if zone.name == "station"));
+        assert!(matches!(flow.destination, OdEndpoint::Door(pos) if pos == Vec2::new(-60.0, 40.0)));
+        assert_eq!(flow.trips_per_hour, 600.0);
+        assert_eq!((flow.from, flow.until), (None, None));
+
+        let flow = &matrix.flows[1];
+        assert_eq!((flow.from, flow.until), (Some(0.0), Some(300.0)));
+        assert_eq!(flow.rate(100.0), 1200.0 / 3600.0);
+        assert_eq!(flow.rate(300.0), 0.0);
+
+        let flow = &matrix.flows[2];
+        assert_eq!((flow.from, flow.until), (None, Some(100.0)));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for (contents, error) in [
+            ("station,north,60", "line 1: unknown zone \"north\""),
+            ("station,1 2,-5", "line 1: negative trips per hour -5"),
+            (
+                "station,1 2,60\nstation,1 2,lots",
+                "line 2: invalid trips per hour \"lots\"",
+            ),
+            ("station,1 2", "line 1: expected 3 to 5 fields, found 2"),
+            ("station,1 2,60,soon", "line 1: invalid time \"soon\""),
+            (
+                "station,1 2,60,300,300",
+                "line 1: time window from 300 until 300 is empty",
+            ),
+        ] {
+            let err = OdMatrix::parse(contents, &zones()).unwrap_err();
+            assert_eq!(err.to_string(), error, "parsing {:?}", contents);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! Local crowd density, which slows people down following a speed–density curve.
+
+use super::Person;
+use crate::{config::MovementConfig, spatial_hash::SpatialHash};
+use bevy::{math::Vec3Swizzles, prelude::*};
+use std::f32::consts::PI;
+
+/// People per square unit around a person, counting the person itself.
+#[derive(Component, Default, Deref, Clone, Copy, Debug)]
+pub struct LocalDensity(pub f32);
+
+pub fn estimate_density(
+    config: Res<MovementConfig>,
+    spatial_hash: Res<SpatialHash>,
+    mut people: Query<(&Transform, &mut LocalDensity), With<Person>>,
+) {
+    let radius = config.density.radius;
+    let area = PI * radius * radius;
+    for (transform, mut density) in people.iter_mut() {
+        let count = spatial_hash
+            .within_radius(transform.translation.xy(), radius)
+            .count();
+        density.0 = count as f32 / area;
+    }
+}
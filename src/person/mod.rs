@@ -0,0 +1,465 @@
+pub mod density;
+pub mod orca;
+pub mod social_force;
+
+use std::{collections::HashMap, f32::consts::PI};
+
+use crate::{
+    ai::CrowdAiLabel,
+    building::Building,
+    config::{CrowdConfig, MovementConfig, MovementModel},
+    simulation::SIM_TIMESTEP,
+    spatial_hash::{update_spatial_hash, SpatialHash},
+};
+use bevy::{math::Vec3Swizzles, prelude::*, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use density::{estimate_density, LocalDensity};
+use orca::Agent;
+use serde::{Deserialize, Serialize};
+use social_force::Pedestrian;
+
+/// Turns the walking direction of every person into physics impulses.
+pub struct PersonPlugin;
+
+/// Half size of the box swept ahead of a person to find who to sidestep.
+const SWEEP_HALF_SIZE: f32 = 0.6;
+
+#[derive(SystemLabel)]
+pub enum PersonLabel {
+    SpatialHash,
+    Density,
+    Movement,
+}
+
+impl Plugin for PersonPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CrowdConfig>()
+            .init_resource::<MovementConfig>()
+            .init_resource::<SpatialHash>()
+            .add_system(update_spatial_hash.label(PersonLabel::SpatialHash))
+            .add_system(
+                estimate_density
+                    .label(PersonLabel::Density)
+                    .after(PersonLabel::SpatialHash),
+            )
+            .add_system(
+                movement
+                    .label(PersonLabel::Movement)
+                    .after(PersonLabel::Density)
+                    .after(CrowdAiLabel::PersonActions),
+            );
+    }
+}
+
+#[derive(Component, Default)]
+pub struct Person {
+    pub state: PersonState,
+}
+
+/// Walking traits of a person, drawn from the `WalkerConfig` distributions when it is spawned.
+/// People without one walk with the default traits.
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Walker {
+    pub preferred_speed: f32,
+    pub max_accel: f32,
+    /// Extra distance kept from other people.
+    pub personal_space: f32,
+    /// Seconds spent blocked before looking for another way.
+    pub patience: f32,
+}
+
+impl Default for Walker {
+    fn default() -> Self {
+        Self {
+            preferred_speed: 10.0,
+            max_accel: 10.0,
+            personal_space: 0.0,
+            patience: 1.0,
+        }
+    }
+}
+
+/// Marks a person walking in the group identified by the entity.
+#[derive(Component, Clone, Copy)]
+pub struct GroupMember(pub Entity);
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub enum PersonState {
+    #[default]
+    Standing,
+    /// Walking with this velocity, relative to the preferred speed of the person.
+    Walking(Vec2),
+}
+
+pub fn add_person(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    config: &CrowdConfig,
+    pos: Vec2,
+) -> Entity {
+    commands
+        .spawn()
+        .insert(Person::default())
+        .insert(LocalDensity::default())
+        .insert(RigidBody::Dynamic)
+        .insert(ExternalImpulse::default())
+        .insert(Velocity::zero())
+        .insert(Collider::cuboid(config.half_size, config.half_size))
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(ColliderMassProperties::Mass(config.mass))
+        .insert(Friction {
+            coefficient: 0.0,
+            combine_rule: CoefficientCombineRule::Max,
+        })
+        .insert(Damping {
+            linear_damping: config.linear_damping,
+            ..default()
+        })
+        .insert(LockedAxes::ROTATION_LOCKED)
+        .insert_bundle(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad::default())).into(),
+            material: materials.add(ColorMaterial::from(Color::FUCHSIA)),
+            transform: Transform::from_xyz(pos.x, pos.y, 20.0),
+            ..default()
+        })
+        .id()
+}
+
+type MovementQuery<'a> = (
+    Entity,
+    &'a mut ExternalImpulse,
+    &'a Velocity,
+    &'a Transform,
+    &'a Person,
+    Option<&'a Walker>,
+    Option<&'a LocalDensity>,
+    Option<&'a GroupMember>,
+);
+
+pub fn movement(
+    config: Res<MovementConfig>,
+    crowd_config: Res<CrowdConfig>,
+    spatial_hash: Res<SpatialHash>,
+    mut persons: Query<MovementQuery>,
+    buildings: Query<&Building>,
+    group_members: Query<(&GroupMember, &Transform)>,
+) {
+    let group_centers = if config.model == MovementModel::SocialForce {
+        group_centers(&group_members)
+    } else {
+        HashMap::new()
+    };
+
+    for (entity, mut impulse, velocity, transform, person, walker, density, group_member) in
+        persons.iter_mut()
+    {
+        let mut walker = walker.copied().unwrap_or_default();
+        if let Some(density) = density {
+            walker.preferred_speed *= config.density.speed_factor(**density);
+        }
+        match person.state {
+            PersonState::Walking(walk_vel) => {
+                let pos = transform.translation.xy();
+                let preferred_vel = walker.preferred_speed * walk_vel;
+                impulse.impulse = match config.model {
+                    MovementModel::Rays => rays_impulse(
+                        entity,
+                        pos,
+                        velocity.linvel,
+                        walk_vel,
+                        &walker,
+                        &config,
+                        &crowd_config,
+                        &spatial_hash,
+                        &buildings,
+                    ),
+                    MovementModel::Orca => {
+                        let orca_config = &config.orca;
+                        let agent = Agent {
+                            pos,
+                            vel: velocity.linvel,
+                            radius: orca_config.radius + walker.personal_space,
+                        };
+                        let neighbours: Vec<_> = spatial_hash
+                            .k_nearest(
+                                pos,
+                                orca_config.max_neighbours,
+                                orca_config.neighbour_distance,
+                                Some(entity),
+                            )
+                            .into_iter()
+                            .map(|neighbour| Agent {
+                                pos: neighbour.pos,
+                                vel: neighbour.vel,
+                                radius: orca_config.radius,
+                            })
+                            .collect();
+                        let walls = nearby_walls(pos, orca_config.neighbour_distance, &buildings);
+                        let desired_vel = orca::avoidance_velocity(
+                            &agent,
+                            preferred_vel,
+                            walker.preferred_speed,
+                            &neighbours,
+                            &walls,
+                            orca_config,
+                            SIM_TIMESTEP,
+                        );
+                        velocity_impulse(desired_vel, velocity.linvel, &walker, &crowd_config)
+                    }
+                    MovementModel::SocialForce => {
+                        let social_config = &config.social_force;
+                        let pedestrian = Pedestrian {
+                            pos,
+                            vel: velocity.linvel,
+                            radius: social_config.radius + walker.personal_space,
+                        };
+                        let others: Vec<_> = spatial_hash
+                            .within_radius(pos, social_config.interaction_distance)
+                            .filter(|other| other.entity != entity)
+                            .map(|other| Pedestrian {
+                                pos: other.pos,
+                                vel: other.vel,
+                                radius: social_config.radius,
+                            })
+                            .collect();
+                        let walls =
+                            nearby_walls(pos, social_config.interaction_distance, &buildings);
+                        let group_center = group_member
+                            .and_then(|group_member| group_centers.get(&group_member.0))
+                            .copied();
+                        let acceleration = social_force::social_acceleration(
+                            &pedestrian,
+                            preferred_vel,
+                            walker.max_accel,
+                            &others,
+                            &walls,
+                            group_center,
+                            social_config,
+                        );
+                        acceleration_impulse(acceleration, velocity.linvel, &crowd_config)
+                    }
+                };
+            }
+            PersonState::Standing => {
+                impulse.impulse = -config.stop_gain * velocity.linvel;
+            }
+        }
+    }
+}
+
+/// Impulse bringing the velocity towards `desired_vel`: what the damping takes away at that
+/// velocity, plus a correction limited by the maximum acceleration of the walker.
+pub fn velocity_impulse(
+    desired_vel: Vec2,
+    current_vel: Vec2,
+    walker: &Walker,
+    crowd_config: &CrowdConfig,
+) -> Vec2 {
+    let damping_loss = crowd_config.mass * crowd_config.linear_damping * SIM_TIMESTEP * desired_vel;
+    let correction = (crowd_config.mass * (desired_vel - current_vel))
+        .clamp_length_max(crowd_config.mass * walker.max_accel * SIM_TIMESTEP);
+    damping_loss + correction
+}
+
+/// Impulse giving `acceleration` over one step, compensating for the damping so the force based
+/// model alone decides how people slow down.
+pub fn acceleration_impulse(
+    acceleration: Vec2,
+    current_vel: Vec2,
+    crowd_config: &CrowdConfig,
+) -> Vec2 {
+    crowd_config.mass * (acceleration + crowd_config.linear_damping * current_vel) * SIM_TIMESTEP
+}
+
+pub fn closest_point_on_segment(point: Vec2, start: Vec2, end: Vec2) -> Vec2 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared <= f32::EPSILON {
+        return start;
+    }
+    let t = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    start + t * segment
+}
+
+/// Building walls passing within `distance` of `pos`.
+fn nearby_walls(pos: Vec2, distance: f32, buildings: &Query<&Building>) -> Vec<(Vec2, Vec2)> {
+    buildings
+        .iter()
+        .flat_map(|building| building.edges())
+        .filter(|&(start, end)| closest_point_on_segment(pos, start, end).distance(pos) < distance)
+        .collect()
+}
+
+fn group_centers(group_members: &Query<(&GroupMember, &Transform)>) -> HashMap<Entity, Vec2> {
+    let mut sums: HashMap<Entity, (Vec2, f32)> = HashMap::new();
+    for (group_member, transform) in group_members.iter() {
+        let (sum, count) = sums.entry(group_member.0).or_default();
+        *sum += transform.translation.xy();
+        *count += 1.0;
+    }
+    sums.into_iter()
+        .map(|(group, (sum, count))| (group, sum / count))
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rays_impulse(
+    entity: Entity,
+    pos: Vec2,
+    vel: Vec2,
+    walk_vel: Vec2,
+    walker: &Walker,
+    config: &MovementConfig,
+    crowd_config: &CrowdConfig,
+    spatial_hash: &SpatialHash,
+    buildings: &Query<&Building>,
+) -> Vec2 {
+    if walk_vel == Vec2::ZERO {
+        return velocity_impulse(Vec2::ZERO, vel, walker, crowd_config);
+    }
+    let target_dir = walk_vel.normalize();
+    let current_dir = vel.normalize_or_zero();
+    let collision_avoidance_dir = if config.collision_avoidance {
+        calculate_collision_avoidance_dir(entity, pos, vel, target_dir, config, spatial_hash)
+    } else {
+        Vec2::ZERO
+    };
+    let personal_distance_dir = if config.personal_space {
+        calculate_personal_space_dir(
+            entity,
+            pos,
+            target_dir,
+            walker,
+            config,
+            crowd_config,
+            spatial_hash,
+            buildings,
+        )
+    } else {
+        Vec2::ZERO
+    };
+    let total_dir = target_dir + collision_avoidance_dir + personal_distance_dir;
+    let correction_dir = total_dir - current_dir;
+    let impulse_dir = (total_dir + 2.0 * correction_dir).normalize_or_zero();
+    velocity_impulse(
+        walker.preferred_speed * walk_vel.length() * impulse_dir,
+        vel,
+        walker,
+        crowd_config,
+    )
+}
+
+/// Sweeps a box along the walking direction and sidesteps the first person it would hit.
+fn calculate_collision_avoidance_dir(
+    entity: Entity,
+    current_pos: Vec2,
+    current_vel: Vec2,
+    target_dir: Vec2,
+    config: &MovementConfig,
+    spatial_hash: &SpatialHash,
+) -> Vec2 {
+    let max_toi = config.avoidance_distance;
+    let hit = spatial_hash
+        .within_radius(current_pos, max_toi + 2.0 * SWEEP_HALF_SIZE)
+        .filter(|other| other.entity != entity)
+        .filter_map(|other| {
+            let offset = other.pos - current_pos;
+            let along = offset.dot(target_dir);
+            let side = offset.perp_dot(target_dir).abs();
+            (along > 0.0 && side < 2.0 * SWEEP_HALF_SIZE)
+                .then(|| (other, (along - 2.0 * SWEEP_HALF_SIZE).max(0.0)))
+        })
+        .filter(|(_, toi)| *toi <= max_toi)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    if let Some((collider, toi)) = hit {
+        let relative_vel_dir = (collider.vel - current_vel).normalize_or_zero();
+        let mut collider_side_dir = relative_vel_dir.reject_from(target_dir);
+        if collider_side_dir.length() < 0.1 {
+            let collider_relative_pos = current_pos - collider.pos;
+            collider_side_dir = -collider_relative_pos.reject_from(target_dir);
+        }
+        if collider_side_dir.length() < 0.1 {
+            collider_side_dir = target_dir.perp();
+        }
+
+        -(2.0 * (max_toi - toi) / max_toi + 0.1) * collider_side_dir.normalize()
+    } else {
+        Vec2::ZERO
+    }
+}
+
+/// Casts rays all around and keeps away from the people and walls they hit.
+#[allow(clippy::too_many_arguments)]
+fn calculate_personal_space_dir(
+    entity: Entity,
+    current_pos: Vec2,
+    target_dir: Vec2,
+    walker: &Walker,
+    config: &MovementConfig,
+    crowd_config: &CrowdConfig,
+    spatial_hash: &SpatialHash,
+    buildings: &Query<&Building>,
+) -> Vec2 {
+    let mut personal_distance_dir = Vec2::ZERO;
+    let rays = config.personal_space_rays;
+    let target_dir = target_dir.normalize();
+    let max_toi = config.personal_space_distance + walker.personal_space;
+
+    let people: Vec<_> = spatial_hash
+        .within_radius(current_pos, max_toi + crowd_config.half_size)
+        .filter(|other| other.entity != entity)
+        .map(|other| other.pos)
+        .collect();
+    let walls = nearby_walls(current_pos, max_toi, buildings);
+
+    for ray_index in 0..rays {
+        let ray_dir = target_dir.rotate(Vec2::from_angle(
+            2.0 * PI * (ray_index as f32 / rays as f32),
+        ));
+        let toi = people
+            .iter()
+            .filter_map(|&other_pos| {
+                ray_circle_toi(current_pos, ray_dir, other_pos, crowd_config.half_size)
+            })
+            .chain(
+                walls
+                    .iter()
+                    .filter_map(|&(start, end)| ray_segment_toi(current_pos, ray_dir, start, end)),
+            )
+            .filter(|toi| *toi <= max_toi)
+            .min_by(|a, b| a.total_cmp(b));
+        if let Some(toi) = toi {
+            let dist = toi - 0.4;
+            personal_distance_dir -= ray_dir / (dist * dist);
+        }
+    }
+
+    personal_distance_dir
+}
+
+/// Distance along the ray to a circle, zero if the origin is inside it.
+fn ray_circle_toi(origin: Vec2, dir: Vec2, center: Vec2, radius: f32) -> Option<f32> {
+    let offset = center - origin;
+    let along = offset.dot(dir);
+    let side_squared = offset.length_squared() - along * along;
+    if side_squared > radius * radius {
+        return None;
+    }
+    let half_chord = (radius * radius - side_squared).sqrt();
+    (along + half_chord >= 0.0).then(|| (along - half_chord).max(0.0))
+}
+
+/// Distance along the ray to a segment.
+fn ray_segment_toi(origin: Vec2, dir: Vec2, start: Vec2, end: Vec2) -> Option<f32> {
+    let edge = end - start;
+    let denominator = dir.perp_dot(edge);
+    if denominator.abs() <= f32::EPSILON {
+        return None;
+    }
+    let offset = start - origin;
+    let toi = offset.perp_dot(edge) / denominator;
+    let along_edge = offset.perp_dot(dir) / denominator;
+    (toi >= 0.0 && (0.0..=1.0).contains(&along_edge)).then_some(toi)
+}
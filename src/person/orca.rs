@@ -0,0 +1,350 @@
+//! Optimal Reciprocal Collision Avoidance: every neighbour and wall close by restricts the
+//! velocities a person may pick to a half-plane, and the velocity closest to the preferred one is
+//! found with the linear programs of the RVO2 library. Unlike RVO2, which builds the half-planes
+//! of walls from the obstacle polygons, a wall stands for a still person of no radius at its point
+//! closest to the person, who takes all of the avoidance.
+
+use super::closest_point_on_segment;
+use crate::config::OrcaConfig;
+use bevy::prelude::*;
+
+const EPSILON: f32 = 0.00001;
+
+/// Position, velocity and radius of a person taking part in the avoidance.
+#[derive(Clone, Copy, Debug)]
+pub struct Agent {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub radius: f32,
+}
+
+/// Boundary of a half-plane of allowed velocities, which lie on the left of `direction`.
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    point: Vec2,
+    direction: Vec2,
+}
+
+/// Returns the velocity closest to `preferred_vel` that avoids `neighbours` and the `walls`
+/// segments for the configured time horizons, never faster than `max_speed`.
+pub fn avoidance_velocity(
+    agent: &Agent,
+    preferred_vel: Vec2,
+    max_speed: f32,
+    neighbours: &[Agent],
+    walls: &[(Vec2, Vec2)],
+    config: &OrcaConfig,
+    dt: f32,
+) -> Vec2 {
+    let mut lines = Vec::with_capacity(walls.len() + neighbours.len());
+    for &(start, end) in walls {
+        let closest = closest_point_on_segment(agent.pos, start, end);
+        let wall = Agent {
+            pos: closest,
+            vel: Vec2::ZERO,
+            radius: 0.0,
+        };
+        lines.push(orca_line(
+            agent,
+            &wall,
+            config.obstacle_time_horizon,
+            1.0,
+            dt,
+        ));
+    }
+    let obstacle_lines = lines.len();
+    for other in neighbours {
+        lines.push(orca_line(agent, other, config.time_horizon, 0.5, dt));
+    }
+
+    let mut result = Vec2::ZERO;
+    let line_fail = linear_program2(&lines, max_speed, preferred_vel, false, &mut result);
+    if line_fail < lines.len() {
+        linear_program3(&lines, obstacle_lines, line_fail, max_speed, &mut result);
+    }
+    result
+}
+
+/// Half-plane of velocities avoiding `other` for `time_horizon` seconds, where `agent` takes
+/// `responsibility` of the avoidance (half for people, all of it for walls).
+fn orca_line(
+    agent: &Agent,
+    other: &Agent,
+    time_horizon: f32,
+    responsibility: f32,
+    dt: f32,
+) -> Line {
+    let relative_pos = other.pos - agent.pos;
+    let relative_vel = agent.vel - other.vel;
+    let distance_squared = relative_pos.length_squared();
+    let combined_radius = agent.radius + other.radius;
+    let combined_radius_squared = combined_radius * combined_radius;
+
+    let (direction, u) = if distance_squared > combined_radius_squared {
+        let inv_time_horizon = 1.0 / time_horizon;
+        // Vector from the cutoff center to the relative velocity.
+        let w = relative_vel - inv_time_horizon * relative_pos;
+        let w_length_squared = w.length_squared();
+        let dot = w.dot(relative_pos);
+
+        if dot < 0.0 && dot * dot > combined_radius_squared * w_length_squared {
+            // Project on the cutoff circle.
+            let w_length = w_length_squared.sqrt();
+            let unit_w = w / w_length;
+            (
+                Vec2::new(unit_w.y, -unit_w.x),
+                (combined_radius * inv_time_horizon - w_length) * unit_w,
+            )
+        } else {
+            // Project on the legs of the velocity obstacle.
+            let leg = (distance_squared - combined_radius_squared).sqrt();
+            let direction = if relative_pos.perp_dot(w) > 0.0 {
+                Vec2::new(
+                    relative_pos.x * leg - relative_pos.y * combined_radius,
+                    relative_pos.x * combined_radius + relative_pos.y * leg,
+                ) / distance_squared
+            } else {
+                -Vec2::new(
+                    relative_pos.x * leg + relative_pos.y * combined_radius,
+                    -relative_pos.x * combined_radius + relative_pos.y * leg,
+                ) / distance_squared
+            };
+            (
+                direction,
+                relative_vel.dot(direction) * direction - relative_vel,
+            )
+        }
+    } else {
+        // Already colliding: get apart within one time step.
+        let inv_time_step = 1.0 / dt;
+        let w = relative_vel - inv_time_step * relative_pos;
+        let w_length = w.length();
+        let unit_w = if w_length > EPSILON {
+            w / w_length
+        } else {
+            -relative_pos.normalize_or_zero()
+        };
+        (
+            Vec2::new(unit_w.y, -unit_w.x),
+            (combined_radius * inv_time_step - w_length) * unit_w,
+        )
+    };
+
+    Line {
+        point: agent.vel + responsibility * u,
+        direction,
+    }
+}
+
+/// Solves the program on the line `line_index`, constrained by the lines before it and the
+/// circle of `radius`.
+fn linear_program1(
+    lines: &[Line],
+    line_index: usize,
+    radius: f32,
+    opt_velocity: Vec2,
+    direction_opt: bool,
+    result: &mut Vec2,
+) -> bool {
+    let line = lines[line_index];
+    let dot = line.point.dot(line.direction);
+    let discriminant = dot * dot + radius * radius - line.point.length_squared();
+    if discriminant < 0.0 {
+        // The max speed circle fully invalidates the line.
+        return false;
+    }
+
+    let discriminant_sqrt = discriminant.sqrt();
+    let mut t_left = -dot - discriminant_sqrt;
+    let mut t_right = -dot + discriminant_sqrt;
+
+    for other in &lines[..line_index] {
+        let denominator = line.direction.perp_dot(other.direction);
+        let numerator = other.direction.perp_dot(line.point - other.point);
+        if denominator.abs() <= EPSILON {
+            // The lines are parallel.
+            if numerator < 0.0 {
+                return false;
+            }
+            continue;
+        }
+        let t = numerator / denominator;
+        if denominator >= 0.0 {
+            t_right = t_right.min(t);
+        } else {
+            t_left = t_left.max(t);
+        }
+        if t_left > t_right {
+            return false;
+        }
+    }
+
+    let t = if direction_opt {
+        if opt_velocity.dot(line.direction) > 0.0 {
+            t_right
+        } else {
+            t_left
+        }
+    } else {
+        line.direction
+            .dot(opt_velocity - line.point)
+            .clamp(t_left, t_right)
+    };
+    *result = line.point + t * line.direction;
+    true
+}
+
+/// Returns the index of the first line that could not be satisfied, or the number of lines.
+fn linear_program2(
+    lines: &[Line],
+    radius: f32,
+    opt_velocity: Vec2,
+    direction_opt: bool,
+    result: &mut Vec2,
+) -> usize {
+    *result = if direction_opt {
+        opt_velocity * radius
+    } else if opt_velocity.length_squared() > radius * radius {
+        opt_velocity.normalize() * radius
+    } else {
+        opt_velocity
+    };
+
+    for (line_index, line) in lines.iter().enumerate() {
+        if line.direction.perp_dot(line.point - *result) > 0.0 {
+            let previous_result = *result;
+            if !linear_program1(
+                lines,
+                line_index,
+                radius,
+                opt_velocity,
+                direction_opt,
+                result,
+            ) {
+                *result = previous_result;
+                return line_index;
+            }
+        }
+    }
+    lines.len()
+}
+
+/// Used when the program is infeasible: keeps the obstacle lines and finds the velocity that
+/// violates the other lines the least.
+fn linear_program3(
+    lines: &[Line],
+    obstacle_lines: usize,
+    begin_line: usize,
+    radius: f32,
+    result: &mut Vec2,
+) {
+    let mut distance = 0.0;
+    for line_index in begin_line..lines.len() {
+        let line = lines[line_index];
+        if line.direction.perp_dot(line.point - *result) <= distance {
+            continue;
+        }
+
+        let mut projected_lines = lines[..obstacle_lines].to_vec();
+        for other in &lines[obstacle_lines..line_index] {
+            let determinant = line.direction.perp_dot(other.direction);
+            let point = if determinant.abs() <= EPSILON {
+                if line.direction.dot(other.direction) > 0.0 {
+                    // The lines point in the same direction.
+                    continue;
+                }
+                0.5 * (line.point + other.point)
+            } else {
+                line.point
+                    + (other.direction.perp_dot(line.point - other.point) / determinant)
+                        * line.direction
+            };
+            projected_lines.push(Line {
+                point,
+                direction: (other.direction - line.direction).normalize_or_zero(),
+            });
+        }
+
+        let previous_result = *result;
+        let opt_direction = Vec2::new(-line.direction.y, line.direction.x);
+        if linear_program2(&projected_lines, radius, opt_direction, true, result)
+            < projected_lines.len()
+        {
+            // Can only happen because of floating point errors, keep the previous result.
+            *result = previous_result;
+        }
+        distance = line.direction.perp_dot(line.point - *result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    #[test]
+    fn head_on_agents_pass_each_other() {
+        let config = OrcaConfig::default();
+        let radius = config.radius;
+        let mut agents = [
+            Agent {
+                pos: Vec2::new(-5.0, 0.05),
+                vel: Vec2::ZERO,
+                radius,
+            },
+            Agent {
+                pos: Vec2::new(5.0, -0.05),
+                vel: Vec2::ZERO,
+                radius,
+            },
+        ];
+        let preferred = [Vec2::new(1.3, 0.0), Vec2::new(-1.3, 0.0)];
+        for _ in 0..600 {
+            let velocities = [0, 1].map(|index| {
+                let others = [agents[1 - index]];
+                avoidance_velocity(
+                    &agents[index],
+                    preferred[index],
+                    1.5,
+                    &others,
+                    &[],
+                    &config,
+                    DT,
+                )
+            });
+            for (agent, vel) in agents.iter_mut().zip(velocities) {
+                agent.vel = vel;
+                agent.pos += vel * DT;
+            }
+            assert!(agents[0].pos.distance(agents[1].pos) >= 2.0 * radius - 0.05);
+        }
+        assert!(agents[0].pos.x > 3.0);
+        assert!(agents[1].pos.x < -3.0);
+    }
+
+    #[test]
+    fn agent_near_wall_does_not_walk_into_it() {
+        let config = OrcaConfig {
+            radius: 0.5,
+            ..default()
+        };
+        let agent = Agent {
+            pos: Vec2::ZERO,
+            vel: Vec2::ZERO,
+            radius: config.radius,
+        };
+        let wall = [(Vec2::new(-10.0, 1.0), Vec2::new(10.0, 1.0))];
+        // Half a unit of clearance to cover in the obstacle time horizon.
+        let max_towards_wall = 0.5 / config.obstacle_time_horizon;
+
+        let vel = avoidance_velocity(&agent, Vec2::new(0.0, 2.0), 3.0, &[], &wall, &config, DT);
+        assert!(vel.y > 0.0);
+        assert!(vel.y <= max_towards_wall + 1e-3);
+
+        // Walking along the wall is not slowed down.
+        let vel = avoidance_velocity(&agent, Vec2::new(2.0, 2.0), 3.0, &[], &wall, &config, DT);
+        assert!((vel.x - 2.0).abs() < 1e-3);
+        assert!(vel.y <= max_towards_wall + 1e-3);
+    }
+}
@@ -0,0 +1,194 @@
+//! Helbing's social force model: people accelerate towards their desired velocity and are pushed
+//! away from each other and from walls by forces decaying exponentially with the distance.
+
+use super::closest_point_on_segment;
+use crate::config::SocialForceConfig;
+use bevy::prelude::*;
+
+/// Position, velocity and radius of a person feeling or exerting social forces.
+#[derive(Clone, Copy, Debug)]
+pub struct Pedestrian {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub radius: f32,
+}
+
+/// Returns the acceleration of `pedestrian` due to the driving force towards `desired_vel`, at
+/// most `max_accel`, the repulsion from `others` and the `walls` segments, and the attraction
+/// towards the center of its group if it walks in one.
+pub fn social_acceleration(
+    pedestrian: &Pedestrian,
+    desired_vel: Vec2,
+    max_accel: f32,
+    others: &[Pedestrian],
+    walls: &[(Vec2, Vec2)],
+    group_center: Option<Vec2>,
+    config: &SocialForceConfig,
+) -> Vec2 {
+    let driving =
+        ((desired_vel - pedestrian.vel) / config.relaxation_time).clamp_length_max(max_accel);
+    let walking_dir = desired_vel.normalize_or_zero();
+
+    let mut repulsion = Vec2::ZERO;
+    for other in others {
+        let offset = pedestrian.pos - other.pos;
+        let distance = offset.length();
+        if distance <= f32::EPSILON {
+            continue;
+        }
+        let normal = offset / distance;
+        // People react less to what happens behind them.
+        let cos_angle = -normal.dot(walking_dir);
+        let anisotropy = config.anisotropy + (1.0 - config.anisotropy) * (1.0 + cos_angle) / 2.0;
+        repulsion += anisotropy
+            * config.person_repulsion
+            * ((pedestrian.radius + other.radius - distance) / config.person_range).exp()
+            * normal;
+    }
+
+    for &(start, end) in walls {
+        let offset = pedestrian.pos - closest_point_on_segment(pedestrian.pos, start, end);
+        let distance = offset.length();
+        if distance <= f32::EPSILON {
+            continue;
+        }
+        let normal = offset / distance;
+        repulsion += config.wall_repulsion
+            * ((pedestrian.radius - distance) / config.wall_range).exp()
+            * normal;
+    }
+
+    let cohesion = match group_center {
+        Some(center) if config.group_cohesion => {
+            config.cohesion_strength * (center - pedestrian.pos)
+        }
+        _ => Vec2::ZERO,
+    };
+
+    driving + repulsion + cohesion
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pedestrian(pos: Vec2, vel: Vec2) -> Pedestrian {
+        Pedestrian {
+            pos,
+            vel,
+            radius: SocialForceConfig::default().radius,
+        }
+    }
+
+    /// Acceleration of someone standing at the origin, or walking along +x at the desired velocity
+    /// when `walking`.
+    fn acceleration_at_origin(
+        walking: bool,
+        others: &[Pedestrian],
+        walls: &[(Vec2, Vec2)],
+        group_center: Option<Vec2>,
+        config: &SocialForceConfig,
+    ) -> Vec2 {
+        let vel = if walking { Vec2::X } else { Vec2::ZERO };
+        social_acceleration(
+            &pedestrian(Vec2::ZERO, vel),
+            vel,
+            10.0,
+            others,
+            walls,
+            group_center,
+            config,
+        )
+    }
+
+    #[test]
+    fn driving_force_is_capped_at_max_accel() {
+        let config = SocialForceConfig::default();
+        let standing = pedestrian(Vec2::ZERO, Vec2::ZERO);
+        let fast = social_acceleration(
+            &standing,
+            Vec2::new(10.0, 0.0),
+            3.0,
+            &[],
+            &[],
+            None,
+            &config,
+        );
+        assert!(fast.abs_diff_eq(Vec2::new(3.0, 0.0), 1e-5));
+        let slow =
+            social_acceleration(&standing, Vec2::new(0.5, 0.0), 3.0, &[], &[], None, &config);
+        assert!(slow.abs_diff_eq(Vec2::new(0.5 / config.relaxation_time, 0.0), 1e-5));
+    }
+
+    #[test]
+    fn repulsion_points_away_and_decays_with_distance() {
+        let config = SocialForceConfig::default();
+        let near = acceleration_at_origin(
+            false,
+            &[pedestrian(Vec2::new(2.0, 0.0), Vec2::ZERO)],
+            &[],
+            None,
+            &config,
+        );
+        let far = acceleration_at_origin(
+            false,
+            &[pedestrian(Vec2::new(4.0, 0.0), Vec2::ZERO)],
+            &[],
+            None,
+            &config,
+        );
+        assert!(near.x < 0.0 && near.y.abs() < 1e-5);
+        assert!(far.x < 0.0 && far.y.abs() < 1e-5);
+        assert!(near.length() > far.length());
+        let decay = (-2.0 / config.person_range).exp();
+        assert!((far.length() / near.length() - decay).abs() < 1e-4);
+    }
+
+    #[test]
+    fn repulsion_from_behind_is_weaker() {
+        let config = SocialForceConfig::default();
+        let ahead = acceleration_at_origin(
+            true,
+            &[pedestrian(Vec2::new(2.0, 0.0), Vec2::ZERO)],
+            &[],
+            None,
+            &config,
+        );
+        let behind = acceleration_at_origin(
+            true,
+            &[pedestrian(Vec2::new(-2.0, 0.0), Vec2::ZERO)],
+            &[],
+            None,
+            &config,
+        );
+        assert!(ahead.x < 0.0 && behind.x > 0.0);
+        assert!((behind.length() / ahead.length() - config.anisotropy).abs() < 1e-4);
+    }
+
+    #[test]
+    fn wall_pushes_along_its_normal() {
+        let config = SocialForceConfig::default();
+        let wall = (Vec2::new(-5.0, 1.0), Vec2::new(5.0, 1.0));
+        let accel = acceleration_at_origin(false, &[], &[wall], None, &config);
+        assert!(accel.y < 0.0);
+        assert!(accel.x.abs() < 1e-5);
+        let expected = config.wall_repulsion * ((config.radius - 1.0) / config.wall_range).exp();
+        assert!((accel.y + expected).abs() < 1e-3);
+    }
+
+    #[test]
+    fn cohesion_only_when_enabled() {
+        let center = Some(Vec2::new(3.0, 0.0));
+        let config = SocialForceConfig::default();
+        let pulled = acceleration_at_origin(false, &[], &[], center, &config);
+        assert!(pulled.abs_diff_eq(Vec2::new(3.0 * config.cohesion_strength, 0.0), 1e-5));
+        let alone = acceleration_at_origin(false, &[], &[], None, &config);
+        assert_eq!(alone, Vec2::ZERO);
+        let config = SocialForceConfig {
+            group_cohesion: false,
+            ..default()
+        };
+        let unpulled = acceleration_at_origin(false, &[], &[], center, &config);
+        assert_eq!(unpulled, Vec2::ZERO);
+    }
+}
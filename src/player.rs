@@ -1,4 +1,59 @@
+use crate::{camera, config::CrowdConfig, controls, person::*};
 use bevy::prelude::*;
 
+/// A person controlled with the keyboard, followed by the camera.
+pub struct PlayerPlugin;
+
 #[derive(Component)]
 pub struct Player;
+
+pub struct PlayerConfig {
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub zoom_out: KeyCode,
+    pub zoom_in: KeyCode,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            up: KeyCode::Comma,
+            down: KeyCode::O,
+            left: KeyCode::A,
+            right: KeyCode::E,
+            zoom_out: KeyCode::K,
+            zoom_in: KeyCode::J,
+        }
+    }
+}
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerConfig>()
+            .add_startup_system(camera::setup)
+            .add_startup_system(spawn_player)
+            .add_system(controls::player_movement.before(PersonLabel::Movement))
+            .add_system(controls::camera_zoom)
+            .add_system(camera::follow_player);
+    }
+}
+
+fn spawn_player(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    config: Res<CrowdConfig>,
+) {
+    let person_entity = add_person(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &config,
+        Vec2::new(0.0, 0.0),
+    );
+    commands.entity(person_entity).insert(Player);
+
+    //crate::debug::collision_scenario(&mut commands, &mut meshes, &mut materials, &config);
+}
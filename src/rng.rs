@@ -0,0 +1,15 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// Random number generator used by every random choice of the simulation, kept as a resource so
+/// runs can be reproduced from a seed and its state can be saved.
+#[derive(Deref, DerefMut, Clone, Serialize, Deserialize)]
+pub struct SimRng(ChaCha8Rng);
+
+impl SimRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
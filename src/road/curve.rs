@@ -0,0 +1,221 @@
+//! Shape of a road between its two nodes: straight, a Bezier curve or a circular arc. Curves are
+//! drawn as lyon paths and sampled as polylines for everything else, vehicle lanes, road areas and
+//! kerbs alike.
+
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Length of the segments curves are sampled with.
+const CURVE_STEP: f32 = 2.0;
+
+/// Sweep angle, in radians, below which an arc is drawn as a straight line.
+const MIN_ARC_SWEEP: f32 = 0.001;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum RoadCurve {
+    #[default]
+    Straight,
+    /// Quadratic Bezier curve pulled towards `control`.
+    Quadratic { control: Vec2 },
+    /// Cubic Bezier curve leaving `from` towards `control_from` and reaching `to` from
+    /// `control_to`.
+    Cubic {
+        control_from: Vec2,
+        control_to: Vec2,
+    },
+    /// Circular arc sweeping `sweep` radians from `from` to `to`, counterclockwise when positive.
+    Arc { sweep: f32 },
+}
+
+impl RoadCurve {
+    /// Returns the center and radius of an arc from `from` to `to`.
+    fn arc_circle(from: Vec2, to: Vec2, sweep: f32) -> (Vec2, f32) {
+        let chord = to - from;
+        let center = (from + to) / 2.0 + chord.perp() / 2.0 / (sweep / 2.0).tan();
+        (center, center.distance(from))
+    }
+
+    fn point(&self, from: Vec2, to: Vec2, t: f32) -> Vec2 {
+        let s = 1.0 - t;
+        match *self {
+            RoadCurve::Quadratic { control } => s * s * from + 2.0 * s * t * control + t * t * to,
+            RoadCurve::Cubic {
+                control_from,
+                control_to,
+            } => {
+                s * s * s * from
+                    + 3.0 * s * s * t * control_from
+                    + 3.0 * s * t * t * control_to
+                    + t * t * t * to
+            }
+            RoadCurve::Arc { sweep } if sweep.abs() > MIN_ARC_SWEEP => {
+                let (center, radius) = Self::arc_circle(from, to, sweep);
+                let start = from - center;
+                let angle = start.y.atan2(start.x) + t * sweep;
+                center + radius * Vec2::new(angle.cos(), angle.sin())
+            }
+            _ => from.lerp(to, t),
+        }
+    }
+
+    /// Upper bound of the length of the curve, to choose how finely to sample it.
+    fn approximate_length(&self, from: Vec2, to: Vec2) -> f32 {
+        match *self {
+            RoadCurve::Quadratic { control } => from.distance(control) + control.distance(to),
+            RoadCurve::Cubic {
+                control_from,
+                control_to,
+            } => {
+                from.distance(control_from)
+                    + control_from.distance(control_to)
+                    + control_to.distance(to)
+            }
+            RoadCurve::Arc { sweep } if sweep.abs() > MIN_ARC_SWEEP => {
+                Self::arc_circle(from, to, sweep).1 * sweep.abs()
+            }
+            _ => from.distance(to),
+        }
+    }
+
+    /// The curve as a polyline from `from` to `to`, with segments about `CURVE_STEP` long.
+    pub fn polyline(&self, from: Vec2, to: Vec2) -> Vec<Vec2> {
+        if *self == RoadCurve::Straight {
+            return vec![from, to];
+        }
+        let segments = (self.approximate_length(from, to) / CURVE_STEP)
+            .ceil()
+            .max(1.0) as usize;
+        (0..=segments)
+            .map(|segment| self.point(from, to, segment as f32 / segments as f32))
+            .collect()
+    }
+
+    /// The curve as a lyon path, to draw it.
+    pub fn path(&self, from: Vec2, to: Vec2) -> Path {
+        let mut builder = PathBuilder::new();
+        builder.move_to(from);
+        match *self {
+            RoadCurve::Quadratic { control } => {
+                builder.quadratic_bezier_to(control, to);
+            }
+            RoadCurve::Cubic {
+                control_from,
+                control_to,
+            } => {
+                builder.cubic_bezier_to(control_from, control_to, to);
+            }
+            RoadCurve::Arc { sweep } if sweep.abs() > MIN_ARC_SWEEP => {
+                let (center, radius) = Self::arc_circle(from, to, sweep);
+                builder.arc(center, Vec2::splat(radius), sweep, 0.0);
+            }
+            _ => {
+                builder.line_to(to);
+            }
+        }
+        builder.build()
+    }
+}
+
+pub fn length(points: &[Vec2]) -> f32 {
+    points
+        .windows(2)
+        .map(|pair| pair[0].distance(pair[1]))
+        .sum()
+}
+
+/// The polyline moved `offset` to the right of its direction, with mitered corners.
+pub fn offset(points: &[Vec2], offset: f32) -> Vec<Vec2> {
+    let rights: Vec<Vec2> = points
+        .windows(2)
+        .map(|pair| -(pair[1] - pair[0]).normalize_or_zero().perp())
+        .collect();
+    (0..points.len())
+        .map(|index| {
+            let right = match (
+                index.checked_sub(1).map(|index| rights[index]),
+                rights.get(index),
+            ) {
+                (Some(before), Some(&after)) => {
+                    let miter = (before + after).normalize_or_zero();
+                    // Sharp corners would send the miter far away.
+                    miter / miter.dot(after).max(0.5)
+                }
+                (Some(right), None) | (None, Some(&right)) => right,
+                (None, None) => Vec2::ZERO,
+            };
+            points[index] + offset * right
+        })
+        .collect()
+}
+
+/// The part of the polyline between the distances `start` and `end` along it.
+pub fn section(points: &[Vec2], start: f32, end: f32) -> Vec<Vec2> {
+    let mut section = vec![];
+    let mut distance = 0.0;
+    for pair in points.windows(2) {
+        let length = pair[0].distance(pair[1]);
+        let (from, to) = (distance, distance + length);
+        distance = to;
+        if to < start || from > end || length <= 0.0 {
+            continue;
+        }
+        if section.is_empty() {
+            section.push(pair[0].lerp(pair[1], ((start - from) / length).max(0.0)));
+        }
+        section.push(pair[0].lerp(pair[1], ((end - from) / length).min(1.0)));
+    }
+    section
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::{FRAC_PI_2, PI};
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-3, "{} is not {}", a, b);
+    }
+
+    #[test]
+    fn arc_circle_of_quarter_and_half_turns() {
+        let (center, radius) =
+            RoadCurve::arc_circle(Vec2::new(10.0, 0.0), Vec2::new(0.0, 10.0), FRAC_PI_2);
+        assert_close(center, Vec2::ZERO);
+        assert!((radius - 10.0).abs() < 1e-3);
+
+        let (center, radius) = RoadCurve::arc_circle(Vec2::new(-5.0, 0.0), Vec2::new(5.0, 0.0), PI);
+        assert_close(center, Vec2::ZERO);
+        assert!((radius - 5.0).abs() < 1e-3);
+
+        let arc = RoadCurve::Arc { sweep: FRAC_PI_2 };
+        let points = arc.polyline(Vec2::new(10.0, 0.0), Vec2::new(0.0, 10.0));
+        assert_close(points[0], Vec2::new(10.0, 0.0));
+        assert_close(*points.last().unwrap(), Vec2::new(0.0, 10.0));
+        for point in points {
+            assert!((point.length() - 10.0).abs() < 1e-3);
+            // Counterclockwise through the first quadrant.
+            assert!(point.x >= -1e-3 && point.y >= -1e-3);
+        }
+    }
+
+    #[test]
+    fn offset_right_angle() {
+        let points = [Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0)];
+        let right = offset(&points, 1.0);
+        assert_close(right[0], Vec2::new(0.0, -1.0));
+        assert_close(right[1], Vec2::new(11.0, -1.0));
+        assert_close(right[2], Vec2::new(11.0, 10.0));
+    }
+
+    #[test]
+    fn section_partway() {
+        let points = [Vec2::ZERO, Vec2::new(10.0, 0.0), Vec2::new(10.0, 10.0)];
+        assert_eq!(length(&points), 20.0);
+        let part = section(&points, 5.0, 15.0);
+        assert_eq!(part.len(), 3);
+        assert_close(part[0], Vec2::new(5.0, 0.0));
+        assert_close(part[1], Vec2::new(10.0, 0.0));
+        assert_close(part[2], Vec2::new(10.0, 5.0));
+    }
+}
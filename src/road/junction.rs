@@ -0,0 +1,217 @@
+//! Shape of the junction where streets meet at a road node: a polygon reaching out along every
+//! street as far as its kerbs need to turn the corner to the next one, with rounded corners. The
+//! same polygon is drawn and, with the signals, kept off by people outside of the crosswalks.
+
+use super::RoadCurve;
+use bevy::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+/// Radius of the kerbs turning from a street to the next one.
+const CORNER_RADIUS: f32 = 5.0;
+
+/// Streets meeting at an angle sharper than this are joined by a straight edge, as rounding the
+/// corner would push the junction far along them.
+const MIN_CORNER_ANGLE: f32 = PI / 6.0;
+
+/// A street meeting the others at a junction.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JunctionStreet {
+    /// Node at the other end of the street.
+    pub other: Entity,
+    /// Direction the street leaves the junction in.
+    pub dir: Vec2,
+    /// Half the width of its carriageway.
+    pub half_width: f32,
+    /// Distance from the node to where the street begins, at the edge of the junction.
+    pub setback: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Junction {
+    /// Streets meeting at the junction, counterclockwise.
+    pub streets: Vec<JunctionStreet>,
+    /// Outline of the junction, counterclockwise.
+    pub outline: Vec<Vec2>,
+    /// Parts of the outline between a street and the next one, along which the kerbs go.
+    pub corners: Vec<Vec<Vec2>>,
+}
+
+/// How the kerbs go from the left edge of a street to the right edge of the next one.
+enum Corner {
+    /// Rounded around the point where the edges meet, the curve ending `along` and `next_along`
+    /// from the node along each street.
+    Rounded {
+        point: Vec2,
+        along: f32,
+        next_along: f32,
+    },
+    /// Turning sharply away, at the point where the edges meet.
+    Sharp(Vec2),
+    Straight,
+}
+
+impl Junction {
+    /// The junction at `pos` of the given streets, whatever their setbacks, which are computed
+    /// here.
+    pub fn new(pos: Vec2, mut streets: Vec<JunctionStreet>) -> Self {
+        streets.sort_by(|a, b| angle(a.dir).total_cmp(&angle(b.dir)));
+        let count = streets.len();
+        if count == 1 {
+            // Dead end, rounded off.
+            let street = &mut streets[0];
+            street.setback = 0.0;
+            let left = pos + street.half_width * street.dir.perp();
+            let right = pos - street.half_width * street.dir.perp();
+            let cap = RoadCurve::Arc { sweep: PI }.polyline(left, right);
+            return Self {
+                streets,
+                outline: cap.clone(),
+                corners: vec![cap],
+            };
+        }
+
+        let corners: Vec<Corner> = (0..count)
+            .map(|index| corner(pos, &streets[index], &streets[(index + 1) % count]))
+            .collect();
+        for index in 0..count {
+            let before = &corners[(index + count - 1) % count];
+            let after = &corners[index];
+            let before = match *before {
+                Corner::Rounded { next_along, .. } => next_along,
+                _ => 0.0,
+            };
+            let after = match *after {
+                Corner::Rounded { along, .. } => along,
+                _ => 0.0,
+            };
+            streets[index].setback = before.max(after);
+        }
+
+        let mut outline = vec![];
+        let mut corner_lines = vec![];
+        for (index, corner) in corners.iter().enumerate() {
+            let street = &streets[index];
+            let next = &streets[(index + 1) % count];
+            let left = pos + street.setback * street.dir + street.half_width * street.dir.perp();
+            let next_right = pos + next.setback * next.dir - next.half_width * next.dir.perp();
+            let mut line = vec![left];
+            match *corner {
+                Corner::Rounded { point, .. } => {
+                    let from = point + CORNER_RADIUS * street.dir;
+                    let to = point + CORNER_RADIUS * next.dir;
+                    line.extend(RoadCurve::Quadratic { control: point }.polyline(from, to));
+                }
+                Corner::Sharp(point) => line.push(point),
+                Corner::Straight => {}
+            }
+            line.push(next_right);
+            line.dedup_by(|a, b| a.distance(*b) < 0.01);
+            outline.push(pos + street.setback * street.dir - street.half_width * street.dir.perp());
+            outline.extend(line.iter().copied());
+            // Streets going straight on through the node have no corner between them.
+            if line.len() >= 2 {
+                corner_lines.push(line);
+            }
+        }
+        outline.dedup_by(|a, b| a.distance(*b) < 0.01);
+        Self {
+            streets,
+            outline,
+            corners: corner_lines,
+        }
+    }
+
+    pub fn setback(&self, other: Entity) -> f32 {
+        self.streets
+            .iter()
+            .find(|street| street.other == other)
+            .map_or(0.0, |street| street.setback)
+    }
+
+    pub fn contains(&self, point: Vec2) -> bool {
+        let mut inside = false;
+        for (index, &from) in self.outline.iter().enumerate() {
+            let to = self.outline[(index + 1) % self.outline.len()];
+            if (from.y > point.y) != (to.y > point.y)
+                && point.x < from.x + (point.y - from.y) / (to.y - from.y) * (to.x - from.x)
+            {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+}
+
+fn angle(dir: Vec2) -> f32 {
+    dir.y.atan2(dir.x).rem_euclid(TAU)
+}
+
+/// The corner between the left edge of `street` and the right edge of `next`, the next street
+/// counterclockwise.
+fn corner(pos: Vec2, street: &JunctionStreet, next: &JunctionStreet) -> Corner {
+    let gap = (angle(next.dir) - angle(street.dir)).rem_euclid(TAU);
+    let cross = street.dir.perp_dot(next.dir);
+    if gap < MIN_CORNER_ANGLE || cross.abs() < 1e-4 {
+        return Corner::Straight;
+    }
+    let left = pos + street.half_width * street.dir.perp();
+    let next_right = pos - next.half_width * next.dir.perp();
+    let along = (next_right - left).perp_dot(next.dir) / cross;
+    let next_along = (next_right - left).perp_dot(street.dir) / cross;
+    let point = left + along * street.dir;
+    if gap < PI {
+        Corner::Rounded {
+            point,
+            along: along.max(0.0) + CORNER_RADIUS,
+            next_along: next_along.max(0.0) + CORNER_RADIUS,
+        }
+    } else if point.distance(pos) < street.half_width + next.half_width {
+        Corner::Sharp(point)
+    } else {
+        Corner::Straight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn street(index: u32, dir: Vec2, half_width: f32) -> JunctionStreet {
+        JunctionStreet {
+            other: Entity::from_raw(index),
+            dir,
+            half_width,
+            setback: 0.0,
+        }
+    }
+
+    #[test]
+    fn four_way_junction_is_symmetric() {
+        let dirs = [Vec2::X, Vec2::Y, -Vec2::X, -Vec2::Y];
+        // Given out of order, to be sorted counterclockwise.
+        let streets = [2, 0, 3, 1]
+            .iter()
+            .map(|&index| street(index, dirs[index as usize], 4.0))
+            .collect();
+        let junction = Junction::new(Vec2::ZERO, streets);
+
+        assert_eq!(junction.corners.len(), 4);
+        for (index, street) in junction.streets.iter().enumerate() {
+            assert_eq!(street.other, Entity::from_raw(index as u32));
+            // The kerbs of perpendicular streets meet at the edge of the other street.
+            assert!((street.setback - (4.0 + CORNER_RADIUS)).abs() < 1e-4);
+            assert_eq!(junction.setback(street.other), street.setback);
+        }
+        assert!(junction.contains(Vec2::ZERO));
+        assert!(junction.contains(Vec2::new(8.0, 0.0)));
+        assert!(!junction.contains(Vec2::new(8.0, 8.0)));
+    }
+
+    #[test]
+    fn dead_end_is_capped() {
+        let junction = Junction::new(Vec2::ZERO, vec![street(0, Vec2::X, 3.0)]);
+        assert_eq!(junction.streets[0].setback, 0.0);
+        assert!(junction.contains(Vec2::new(-1.0, 0.0)));
+        assert!(!junction.contains(Vec2::new(-4.0, 0.0)));
+    }
+}
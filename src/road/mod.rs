@@ -0,0 +1,471 @@
+pub mod curve;
+pub mod junction;
+
+use crate::ai::InvalidatePaths;
+use bevy::{prelude::*, utils::HashMap};
+use bevy_prototype_lyon::prelude::*;
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+pub use curve::RoadCurve;
+pub use junction::{Junction, JunctionStreet};
+
+/// Default width of the carriageway of a road, and of the square drawn at nodes no street meets at.
+pub const ROAD_WIDTH: f32 = 20.0;
+pub const DEFAULT_LANES: u32 = 2;
+pub const DEFAULT_SPEED_LIMIT: f32 = 25.0;
+pub const DEFAULT_SIDEWALK_WIDTH: f32 = 4.0;
+
+const CARRIAGEWAY_COLOR: Color = Color::DARK_GRAY;
+const SIDEWALK_COLOR: Color = Color::rgb(0.6, 0.6, 0.55);
+const FOOTPATH_COLOR: Color = Color::rgb(0.7, 0.6, 0.45);
+const PLAZA_COLOR: Color = Color::rgb(0.75, 0.7, 0.6);
+
+#[derive(Component, Clone, Debug)]
+pub struct Road {
+    pub from: Entity,
+    pub to: Entity,
+    pub curve: RoadCurve,
+    pub kind: RoadKind,
+    /// Driving lanes, in both directions together.
+    pub lanes: u32,
+    /// Width of the carriageway, or of the whole footpath or plaza.
+    pub width: f32,
+    /// Vehicles may only drive from `from` to `to`.
+    pub one_way: bool,
+    pub speed_limit: f32,
+    /// Width of the sidewalks on both sides of the carriageway.
+    pub sidewalk_width: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoadKind {
+    /// Vehicles drive on the carriageway, people walk on the sidewalks and cross at crosswalks.
+    #[default]
+    Street,
+    /// Only people, walking anywhere on it.
+    Footpath,
+    /// Open paved area for people, drawn wider than a footpath.
+    Plaza,
+}
+
+impl Road {
+    /// A two way street with the default lanes and widths.
+    pub fn new(from: Entity, to: Entity) -> Self {
+        Self {
+            from,
+            to,
+            curve: RoadCurve::Straight,
+            kind: RoadKind::Street,
+            lanes: DEFAULT_LANES,
+            width: ROAD_WIDTH,
+            one_way: false,
+            speed_limit: DEFAULT_SPEED_LIMIT,
+            sidewalk_width: DEFAULT_SIDEWALK_WIDTH,
+        }
+    }
+
+    pub fn is_street(&self) -> bool {
+        self.kind == RoadKind::Street
+    }
+
+    /// Lanes vehicles may drive in, in each direction they may drive.
+    pub fn lanes_per_direction(&self) -> u32 {
+        if self.one_way {
+            self.lanes.max(1)
+        } else {
+            (self.lanes / 2).max(1)
+        }
+    }
+
+    /// Distance from the middle of the road to the middle of a lane, counted from the right
+    /// kerb, on the right of the direction of travel. Vehicles in a lane the road does not have
+    /// keep to its innermost one.
+    pub fn lane_offset(&self, lane: u32) -> f32 {
+        let lane_width = self.width / self.lanes.max(1) as f32;
+        let lane = lane.min(self.lanes_per_direction() - 1);
+        self.width / 2.0 - (lane as f32 + 0.5) * lane_width
+    }
+}
+
+#[derive(Component, Clone, Serialize, Deserialize)]
+pub struct RoadNode {
+    pub pos: Vec2,
+}
+
+/// Sidewalks drawn along a street, as a child of the road.
+#[derive(Component)]
+pub struct Sidewalks;
+
+pub fn on_add_road(
+    mut commands: Commands,
+    added_road: Query<(Entity, &Road), Added<Road>>,
+    road_nodes: Query<&RoadNode>,
+) {
+    for (road_entity, road) in added_road.iter() {
+        if let (Ok(from), Ok(to)) = (road_nodes.get(road.from), road_nodes.get(road.to)) {
+            draw_road(&mut commands, road_entity, road, from.pos, to.pos);
+        } else {
+            warn!("Road {:?} references a missing node", road_entity);
+        }
+    }
+}
+
+pub fn on_change_road(
+    mut commands: Commands,
+    roads: Query<(Entity, &Road, ChangeTrackers<Road>, Option<&Children>)>,
+    road_nodes: Query<(&RoadNode, ChangeTrackers<RoadNode>)>,
+    sidewalks: Query<(), With<Sidewalks>>,
+) {
+    for (road_entity, road, road_tracker, children) in roads.iter() {
+        if road_tracker.is_added() {
+            continue;
+        }
+        if let (Ok((from, from_tracker)), Ok((to, to_tracker))) =
+            (road_nodes.get(road.from), road_nodes.get(road.to))
+        {
+            if road_tracker.is_changed() || from_tracker.is_changed() || to_tracker.is_changed() {
+                for &child in children.into_iter().flat_map(|children| children.iter()) {
+                    if sidewalks.get(child).is_ok() {
+                        commands.entity(child).despawn_recursive();
+                    }
+                }
+                draw_road(&mut commands, road_entity, road, from.pos, to.pos);
+            }
+        }
+    }
+}
+
+/// Shape drawn at a road node, as the outline of its junction relative to the node.
+#[derive(Component, PartialEq)]
+pub struct JunctionShape(Vec<Vec2>);
+
+/// Draws the junction of every road node, redrawing it when the streets meeting there change.
+/// Nodes no street meets at are drawn as a square, to see them in the editor.
+pub fn draw_junctions(
+    mut commands: Commands,
+    graph: Res<RoadGraph>,
+    nodes: Query<(Entity, &RoadNode, Option<&JunctionShape>)>,
+) {
+    for (node_entity, node, current) in nodes.iter() {
+        let outline = match graph.junction(node_entity) {
+            Some(junction) => junction
+                .outline
+                .iter()
+                .map(|&point| point - node.pos)
+                .collect(),
+            None => {
+                let half = ROAD_WIDTH / 2.0;
+                vec![
+                    Vec2::new(-half, -half),
+                    Vec2::new(half, -half),
+                    Vec2::new(half, half),
+                    Vec2::new(-half, half),
+                ]
+            }
+        };
+        let shape = JunctionShape(outline);
+        if current == Some(&shape) {
+            continue;
+        }
+        let polygon = shapes::Polygon {
+            points: shape.0.clone(),
+            closed: true,
+        };
+        commands
+            .entity(node_entity)
+            .insert_bundle(GeometryBuilder::build_as(
+                &polygon,
+                DrawMode::Fill(FillMode::color(CARRIAGEWAY_COLOR)),
+                Transform::from_xyz(node.pos.x, node.pos.y, -10.0),
+            ))
+            .insert(shape);
+    }
+}
+
+pub fn on_change_road_node(
+    mut changed_road_node: Query<(&RoadNode, &mut Transform), Changed<RoadNode>>,
+) {
+    for (node, mut transform) in changed_road_node.iter_mut() {
+        transform.translation.x = node.pos.x;
+        transform.translation.y = node.pos.y;
+    }
+}
+
+pub fn on_remove_road_node(
+    mut commands: Commands,
+    removed_road_nodes: RemovedComponents<RoadNode>,
+    roads: Query<(Entity, &Road)>,
+    mut invalidate_paths: EventWriter<InvalidatePaths>,
+) {
+    let removed: Vec<_> = removed_road_nodes.iter().collect();
+    if removed.is_empty() {
+        return;
+    }
+    for (road_entity, road) in roads.iter() {
+        if removed.contains(&road.from) || removed.contains(&road.to) {
+            commands.entity(road_entity).despawn_recursive();
+        }
+    }
+    invalidate_paths.send(InvalidatePaths);
+}
+
+/// Draws the carriageway, footpath or plaza on the road entity, and the sidewalks of a street
+/// below it.
+fn draw_road(
+    commands: &mut Commands,
+    road_entity: Entity,
+    road: &Road,
+    from_pos: Vec2,
+    to_pos: Vec2,
+) {
+    let color = match road.kind {
+        RoadKind::Street => CARRIAGEWAY_COLOR,
+        RoadKind::Footpath => FOOTPATH_COLOR,
+        RoadKind::Plaza => PLAZA_COLOR,
+    };
+    let path = road.curve.path(from_pos, to_pos);
+    commands
+        .entity(road_entity)
+        .insert_bundle(GeometryBuilder::build_as(
+            &path,
+            DrawMode::Stroke(StrokeMode::new(color, road.width)),
+            Transform::from_xyz(0.0, 0.0, -10.0),
+        ));
+    if road.is_street() && road.sidewalk_width > 0.0 {
+        commands.entity(road_entity).add_children(|children| {
+            children
+                .spawn_bundle(GeometryBuilder::build_as(
+                    &path,
+                    DrawMode::Stroke(StrokeMode::new(
+                        SIDEWALK_COLOR,
+                        road.width + 2.0 * road.sidewalk_width,
+                    )),
+                    Transform::from_xyz(0.0, 0.0, -1.0),
+                ))
+                .insert(Sidewalks);
+        });
+    }
+}
+
+/// Nodes and the roads between them. Rebuilt every frame, as road networks are small.
+#[derive(Default)]
+pub struct RoadGraph {
+    positions: HashMap<Entity, Vec2>,
+    /// Nodes vehicles may drive to from every node.
+    neighbours: HashMap<Entity, Vec<Entity>>,
+    /// Other ends of the streets meeting at every node, whichever way they may be driven.
+    streets: HashMap<Entity, Vec<Entity>>,
+    /// The road between two nodes and its middle line, sampled from the first node to the
+    /// second.
+    roads: HashMap<(Entity, Entity), (Road, Vec<Vec2>)>,
+    junctions: HashMap<Entity, Junction>,
+}
+
+impl RoadGraph {
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.neighbours.clear();
+        self.streets.clear();
+        self.roads.clear();
+        self.junctions.clear();
+    }
+
+    pub fn add_node(&mut self, node: Entity, pos: Vec2) {
+        self.positions.insert(node, pos);
+    }
+
+    /// Adds a road between two nodes added before, which vehicles may drive on if it is a
+    /// street, one way or both.
+    pub fn add_road(&mut self, road: &Road) {
+        let (from, to) = (road.from, road.to);
+        let (from_pos, to_pos) = match (self.pos(from), self.pos(to)) {
+            (Some(from_pos), Some(to_pos)) => (from_pos, to_pos),
+            _ => return,
+        };
+        let points = road.curve.polyline(from_pos, to_pos);
+        let reversed = points.iter().rev().copied().collect();
+        self.roads.insert((from, to), (road.clone(), points));
+        self.roads.insert((to, from), (road.clone(), reversed));
+        if !road.is_street() {
+            return;
+        }
+        self.streets.entry(from).or_default().push(to);
+        self.streets.entry(to).or_default().push(from);
+        self.neighbours.entry(from).or_default().push(to);
+        if !road.one_way {
+            self.neighbours.entry(to).or_default().push(from);
+        }
+    }
+
+    /// Shapes the junction at a node from the streets meeting there, once all the roads are
+    /// added.
+    pub fn add_junction(&mut self, node: Entity) {
+        let pos = match self.pos(node) {
+            Some(pos) => pos,
+            None => return,
+        };
+        let mut others = self.streets(node).to_vec();
+        others.sort();
+        others.dedup();
+        let streets: Vec<JunctionStreet> = others
+            .into_iter()
+            .filter_map(|other| {
+                let points = self.centerline(node, other)?;
+                Some(JunctionStreet {
+                    other,
+                    dir: (points[1] - pos).normalize_or_zero(),
+                    half_width: self.road(node, other)?.width / 2.0,
+                    setback: 0.0,
+                })
+            })
+            .filter(|street| street.dir != Vec2::ZERO)
+            .collect();
+        if !streets.is_empty() {
+            self.junctions.insert(node, Junction::new(pos, streets));
+        }
+    }
+
+    pub fn pos(&self, node: Entity) -> Option<Vec2> {
+        self.positions.get(&node).copied()
+    }
+
+    pub fn neighbours(&self, node: Entity) -> &[Entity] {
+        self.neighbours
+            .get(&node)
+            .map_or(&[], |neighbours| neighbours)
+    }
+
+    pub fn streets(&self, node: Entity) -> &[Entity] {
+        self.streets.get(&node).map_or(&[], |streets| streets)
+    }
+
+    /// The road between two nodes, in either direction.
+    pub fn road(&self, from: Entity, to: Entity) -> Option<&Road> {
+        self.roads.get(&(from, to)).map(|(road, _)| road)
+    }
+
+    /// Middle line of the road between two nodes, from `from` to `to`.
+    pub fn centerline(&self, from: Entity, to: Entity) -> Option<&[Vec2]> {
+        self.roads.get(&(from, to)).map(|(_, points)| &points[..])
+    }
+
+    /// The junction at `node`, if streets meet there.
+    pub fn junction(&self, node: Entity) -> Option<&Junction> {
+        self.junctions.get(&node)
+    }
+
+    /// Distance from `node` to where the street to `other` leaves its junction.
+    pub fn junction_setback(&self, node: Entity, other: Entity) -> f32 {
+        self.junction(node)
+            .map_or(0.0, |junction| junction.setback(other))
+    }
+
+    /// Nodes vehicles may drive away from, in a stable order.
+    pub fn connected_nodes(&self) -> Vec<Entity> {
+        let mut nodes: Vec<_> = self.neighbours.keys().copied().collect();
+        nodes.sort();
+        nodes
+    }
+
+    /// Returns the nodes of the fastest route from `from` to `to` within the speed limits, both
+    /// included.
+    pub fn shortest_route(&self, from: Entity, to: Entity) -> Option<Vec<Entity>> {
+        let mut durations = HashMap::default();
+        let mut previous = HashMap::default();
+        let mut open = BinaryHeap::new();
+        durations.insert(from, 0.0);
+        open.push((Reverse(OrderedFloat(0.0)), from));
+
+        while let Some((Reverse(OrderedFloat(duration)), node)) = open.pop() {
+            if node == to {
+                let mut route = vec![to];
+                while let Some(&node) = previous.get(route.last().unwrap()) {
+                    route.push(node);
+                }
+                route.reverse();
+                return Some(route);
+            }
+            if duration > durations[&node] {
+                continue;
+            }
+            for &neighbour in self.neighbours(node) {
+                let (road, points) = self.roads.get(&(node, neighbour))?;
+                let neighbour_duration =
+                    duration + curve::length(points) / road.speed_limit.max(0.1);
+                if durations
+                    .get(&neighbour)
+                    .is_none_or(|&known| neighbour_duration < known)
+                {
+                    durations.insert(neighbour, neighbour_duration);
+                    previous.insert(neighbour, node);
+                    open.push((Reverse(OrderedFloat(neighbour_duration)), neighbour));
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the given lane of the roads along `route`, counted from the right kerb, with its
+    /// corners where the lanes of consecutive roads meet, and the speed limit from every point
+    /// on.
+    pub fn lane_points(&self, route: &[Entity], lane: u32) -> Vec<(Vec2, f32)> {
+        let legs: Vec<(Vec<Vec2>, f32)> = route
+            .windows(2)
+            .filter_map(|pair| {
+                let (road, points) = self.roads.get(&(pair[0], pair[1]))?;
+                let lane = curve::offset(points, road.lane_offset(lane));
+                (lane.len() >= 2).then_some((lane, road.speed_limit))
+            })
+            .collect();
+
+        let mut points = vec![];
+        if let Some((lane, speed_limit)) = legs.first() {
+            points.push((lane[0], *speed_limit));
+        }
+        for (index, (lane, speed_limit)) in legs.iter().enumerate() {
+            let inner = &lane[1..lane.len() - 1];
+            points.extend(inner.iter().map(|&point| (point, *speed_limit)));
+            let (from, to) = (lane[lane.len() - 2], lane[lane.len() - 1]);
+            let (next_from, next_to, next_speed_limit) = match legs.get(index + 1) {
+                Some((next, next_speed_limit)) => (next[0], next[1], *next_speed_limit),
+                None => {
+                    points.push((to, *speed_limit));
+                    continue;
+                }
+            };
+            let dir = (to - from).normalize();
+            let next_dir = (next_to - next_from).normalize();
+            let cross = dir.perp_dot(next_dir);
+            if cross.abs() < 0.01 {
+                // Straight on, or turning back at a dead end.
+                points.push((to, next_speed_limit));
+                if dir.dot(next_dir) < 0.0 {
+                    points.push((next_from, next_speed_limit));
+                }
+            } else {
+                let t = (next_from - from).perp_dot(next_dir) / cross;
+                points.push((from + t * dir, next_speed_limit));
+            }
+        }
+        points
+    }
+}
+
+pub fn update_road_graph(
+    mut graph: ResMut<RoadGraph>,
+    nodes: Query<(Entity, &RoadNode)>,
+    roads: Query<&Road>,
+) {
+    graph.clear();
+    for (entity, node) in nodes.iter() {
+        graph.add_node(entity, node.pos);
+    }
+    for road in roads.iter() {
+        graph.add_road(road);
+    }
+    for (entity, _) in nodes.iter() {
+        graph.add_junction(entity);
+    }
+}
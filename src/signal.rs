@@ -0,0 +1,540 @@
+//! Traffic signals at the intersections, where two streets or more meet. Every street gets green
+//! in turn, then all the crosswalks get the walk signal at once while vehicles wait. Outside of
+//! the crosswalks of the signals, kerbs keep people off the carriageways.
+
+use crate::{
+    city::CityLabel,
+    config::SignalConfig,
+    person::closest_point_on_segment,
+    road::{curve, Junction, Road, RoadGraph, RoadNode},
+    simulation::SimClock,
+};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_prototype_lyon::prelude::{FillMode, *};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Distance between two samples when checking whether a segment stays off the roads.
+const SAMPLE_STEP: f32 = 0.5;
+
+/// Side of the square cells the road areas are sorted into.
+const ROAD_CELL_SIZE: f32 = 10.0;
+
+const WALK_COLOR: Color = Color::WHITE;
+const DONT_WALK_COLOR: Color = Color::SILVER;
+
+pub struct SignalPlugin;
+
+#[derive(SystemLabel)]
+pub enum SignalLabel {
+    Update,
+    RoadAreas,
+}
+
+impl Plugin for SignalPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SignalConfig>()
+            .init_resource::<RoadGraph>()
+            .init_resource::<RoadAreas>()
+            .init_resource::<StopLines>()
+            .add_system(
+                update_signals
+                    .label(SignalLabel::Update)
+                    .after(CityLabel::RoadGraph),
+            )
+            .add_system(
+                update_road_areas
+                    .label(SignalLabel::RoadAreas)
+                    .after(SignalLabel::Update),
+            )
+            .add_system(color_crosswalks.after(SignalLabel::Update))
+            .add_system(update_kerbs.after(CityLabel::RoadGraph));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignalPhase {
+    /// Vehicles coming in on the approach at this index may go.
+    Green(usize),
+    /// People may cross on every crosswalk of the intersection.
+    Walk,
+    /// Everything is red.
+    Clearance,
+}
+
+/// A street meeting the others at an intersection.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Approach {
+    /// Direction the street leaves the intersection in.
+    dir: Vec2,
+    /// Half the width of its carriageway.
+    half_width: f32,
+    /// Distance from the node to the crosswalk, at the edge of the junction.
+    setback: f32,
+    /// Whether vehicles may drive in to the intersection on it.
+    incoming: bool,
+}
+
+/// Signal controller of the road node it is on.
+#[derive(Component, Debug)]
+pub struct SignalController {
+    /// Streets meeting at the intersection, the incoming ones getting green in this order.
+    approaches: Vec<Approach>,
+    /// Index of the current phase in the cycle: a green phase per incoming approach and the walk
+    /// phase, each followed by a clearance phase.
+    current_phase: usize,
+    /// Seconds spent in the current phase.
+    phase_time: f32,
+}
+
+/// Where a signal controller is in its cycle, as saved in snapshots.
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SignalTiming {
+    pub current_phase: usize,
+    pub phase_time: f32,
+}
+
+impl SignalController {
+    pub fn timing(&self) -> SignalTiming {
+        SignalTiming {
+            current_phase: self.current_phase,
+            phase_time: self.phase_time,
+        }
+    }
+
+    fn new(approaches: Vec<Approach>) -> Self {
+        Self {
+            approaches,
+            current_phase: 0,
+            phase_time: 0.0,
+        }
+    }
+
+    fn incoming(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.approaches.len()).filter(|&index| self.approaches[index].incoming)
+    }
+
+    pub fn phase(&self) -> SignalPhase {
+        match self.current_phase {
+            phase if phase % 2 == 1 => SignalPhase::Clearance,
+            phase => match self.incoming().nth(phase / 2) {
+                Some(approach) => SignalPhase::Green(approach),
+                None => SignalPhase::Walk,
+            },
+        }
+    }
+
+    fn advance(&mut self, dt: f32, config: &SignalConfig) {
+        self.phase_time += dt;
+        let duration = match self.phase() {
+            SignalPhase::Green(_) => config.green_time,
+            SignalPhase::Walk => config.walk_time,
+            SignalPhase::Clearance => config.clearance_time,
+        };
+        if self.phase_time >= duration {
+            self.phase_time -= duration;
+            self.current_phase = (self.current_phase + 1) % (2 * (self.incoming().count() + 1));
+        }
+    }
+}
+
+/// Stripes of a crosswalk, drawn as a child of the road node.
+#[derive(Component, Debug)]
+pub struct CrosswalkMarking {
+    walk: bool,
+}
+
+/// Area across a road next to an intersection, where people may cross during the walk phase.
+#[derive(Clone, Copy, Debug)]
+pub struct Crosswalk {
+    pub center: Vec2,
+    /// Direction across the road.
+    pub across: Vec2,
+    pub half_length: f32,
+    pub half_width: f32,
+    pub walk: bool,
+}
+
+impl Crosswalk {
+    pub fn contains(&self, point: Vec2) -> bool {
+        let offset = point - self.center;
+        offset.dot(self.across).abs() <= self.half_length
+            && offset.perp_dot(self.across).abs() <= self.half_width
+    }
+
+    /// Where to wait for the walk signal, on the side of the road `side` is on.
+    pub fn kerb(&self, side: Vec2, margin: f32) -> Vec2 {
+        let sign = (side - self.center).dot(self.across).signum();
+        self.center + sign * (self.half_length + margin) * self.across
+    }
+}
+
+/// Carriageways and junctions of the streets, which people may only walk on at a crosswalk.
+/// Empty when signals are disabled.
+#[derive(Default)]
+pub struct RoadAreas {
+    /// Segments of the middle line of every street and half the width of its carriageway.
+    roads: Vec<(Vec2, Vec2, f32)>,
+    /// Junctions of every node streets meet at.
+    junctions: Vec<Junction>,
+    /// Indices of the road segments and of the junctions overlapping every cell, as the
+    /// pathfinder checks every node it expands.
+    cells: HashMap<(i32, i32), (Vec<usize>, Vec<usize>)>,
+    crosswalks: Vec<Crosswalk>,
+}
+
+impl RoadAreas {
+    pub fn clear(&mut self) {
+        self.roads.clear();
+        self.junctions.clear();
+        self.cells.clear();
+        self.crosswalks.clear();
+    }
+
+    /// Adds a segment of the middle line of a street.
+    pub fn add_road(&mut self, from: Vec2, to: Vec2, half_width: f32) {
+        let index = self.roads.len();
+        let (min, max) = (from.min(to) - half_width, from.max(to) + half_width);
+        for cell in cells_between(min, max) {
+            self.cells.entry(cell).or_default().0.push(index);
+        }
+        self.roads.push((from, to, half_width));
+    }
+
+    pub fn add_junction(&mut self, junction: Junction) {
+        let index = self.junctions.len();
+        let min = junction.outline.iter().copied().reduce(Vec2::min);
+        let max = junction.outline.iter().copied().reduce(Vec2::max);
+        if let (Some(min), Some(max)) = (min, max) {
+            for cell in cells_between(min, max) {
+                self.cells.entry(cell).or_default().1.push(index);
+            }
+        }
+        self.junctions.push(junction);
+    }
+
+    pub fn add_crosswalk(&mut self, crosswalk: Crosswalk) {
+        self.crosswalks.push(crosswalk);
+    }
+
+    pub fn on_road(&self, point: Vec2) -> bool {
+        let (roads, junctions) = match self.cells.get(&cell(point)) {
+            Some(cell) => cell,
+            None => return false,
+        };
+        roads.iter().any(|&index| {
+            let (from, to, half_width) = self.roads[index];
+            closest_point_on_segment(point, from, to).distance(point) < half_width
+        }) || junctions
+            .iter()
+            .any(|&index| self.junctions[index].contains(point))
+    }
+
+    pub fn crosswalk_at(&self, point: Vec2) -> Option<&Crosswalk> {
+        self.crosswalks
+            .iter()
+            .find(|crosswalk| crosswalk.contains(point))
+    }
+
+    /// The crosswalk centered on `center`, as stored in the plans of the people waiting for it.
+    pub fn crosswalk(&self, center: Vec2) -> Option<&Crosswalk> {
+        self.crosswalks
+            .iter()
+            .find(|crosswalk| crosswalk.center.distance(center) < 0.1)
+    }
+
+    pub fn walkable(&self, point: Vec2) -> bool {
+        !self.on_road(point) || self.crosswalk_at(point).is_some()
+    }
+
+    /// Whether the segment crosses a road outside of the crosswalks.
+    pub fn blocks(&self, from: Vec2, to: Vec2) -> bool {
+        samples(from, to).any(|point| !self.walkable(point))
+    }
+
+    /// The crosswalk the segment crosses the road on, if it does.
+    pub fn crosswalk_on(&self, from: Vec2, to: Vec2) -> Option<&Crosswalk> {
+        samples(from, to)
+            .filter(|&point| self.on_road(point))
+            .find_map(|point| self.crosswalk_at(point))
+    }
+}
+
+fn cell(pos: Vec2) -> (i32, i32) {
+    let cell = (pos / ROAD_CELL_SIZE).floor();
+    (cell.x as i32, cell.y as i32)
+}
+
+fn cells_between(min: Vec2, max: Vec2) -> impl Iterator<Item = (i32, i32)> {
+    let ((min_x, min_y), (max_x, max_y)) = (cell(min), cell(max));
+    (min_x..=max_x).flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+}
+
+fn samples(from: Vec2, to: Vec2) -> impl Iterator<Item = Vec2> {
+    let samples = (from.distance(to) / SAMPLE_STEP).ceil().max(1.0) as usize;
+    (0..=samples).map(move |sample| from.lerp(to, sample as f32 / samples as f32))
+}
+
+/// Where vehicles coming in `dir` must stop, as their street does not have green.
+#[derive(Clone, Copy, Debug)]
+pub struct StopLine {
+    pub pos: Vec2,
+    pub dir: Vec2,
+    /// Half the width of the carriageway of the street.
+    pub half_width: f32,
+}
+
+#[derive(Default, Deref, DerefMut)]
+pub struct StopLines(pub Vec<StopLine>);
+
+type SignalNodeQuery<'a> = (
+    Entity,
+    Option<&'a mut SignalController>,
+    Option<&'a Children>,
+    Option<&'a SignalTiming>,
+);
+
+/// Puts a signal controller on every intersection, rebuilding it when its streets change, and runs
+/// its phases. A new controller starts from the `SignalTiming` of its node if it has one.
+pub fn update_signals(
+    mut commands: Commands,
+    config: Res<SignalConfig>,
+    clock: Res<SimClock>,
+    graph: Res<RoadGraph>,
+    mut nodes: Query<SignalNodeQuery, With<RoadNode>>,
+    markings: Query<(), With<CrosswalkMarking>>,
+) {
+    let dt = clock.delta().as_secs_f32();
+    for (entity, controller, children, timing) in nodes.iter_mut() {
+        let approaches: Vec<Approach> = match graph.junction(entity) {
+            Some(junction) if config.enabled => junction
+                .streets
+                .iter()
+                .map(|street| Approach {
+                    dir: street.dir,
+                    half_width: street.half_width,
+                    setback: street.setback,
+                    incoming: graph.neighbours(street.other).contains(&entity),
+                })
+                .collect(),
+            _ => vec![],
+        };
+
+        if let Some(mut controller) = controller {
+            if controller.approaches == approaches {
+                controller.advance(dt, &config);
+                continue;
+            }
+            commands.entity(entity).remove::<SignalController>();
+            for &child in children.into_iter().flat_map(|children| children.iter()) {
+                if markings.get(child).is_ok() {
+                    commands.entity(child).despawn_recursive();
+                }
+            }
+        }
+        if approaches.len() < 2 {
+            continue;
+        }
+
+        for approach in &approaches {
+            let offset = (approach.setback + config.crosswalk_width / 2.0) * approach.dir;
+            let stripes = shapes::Rectangle {
+                extents: Vec2::new(config.crosswalk_width, 2.0 * approach.half_width),
+                origin: RectangleOrigin::Center,
+            };
+            commands.entity(entity).add_children(|children| {
+                children
+                    .spawn_bundle(GeometryBuilder::build_as(
+                        &stripes,
+                        DrawMode::Fill(FillMode::color(DONT_WALK_COLOR)),
+                        Transform::from_xyz(offset.x, offset.y, 1.0).with_rotation(
+                            Quat::from_rotation_z(approach.dir.y.atan2(approach.dir.x)),
+                        ),
+                    ))
+                    .insert(CrosswalkMarking { walk: false });
+            });
+        }
+        let mut controller = SignalController::new(approaches);
+        if let Some(timing) = timing {
+            // Restored from a snapshot, running this step as the saved controller would have.
+            controller.current_phase =
+                timing.current_phase % (2 * (controller.incoming().count() + 1));
+            controller.phase_time = timing.phase_time;
+            controller.advance(dt, &config);
+            commands.entity(entity).remove::<SignalTiming>();
+        }
+        commands.entity(entity).insert(controller);
+    }
+}
+
+/// Rebuilds the road areas and the stop lines from the streets and the signal phases.
+pub fn update_road_areas(
+    config: Res<SignalConfig>,
+    graph: Res<RoadGraph>,
+    mut road_areas: ResMut<RoadAreas>,
+    mut stop_lines: ResMut<StopLines>,
+    roads: Query<&Road>,
+    signals: Query<(Entity, &SignalController)>,
+) {
+    road_areas.clear();
+    stop_lines.clear();
+    if !config.enabled {
+        return;
+    }
+
+    let mut junctions = HashSet::default();
+    for road in roads.iter().filter(|road| road.is_street()) {
+        if let Some(points) = graph.centerline(road.from, road.to) {
+            let half_width = road.width / 2.0;
+            for pair in points.windows(2) {
+                road_areas.add_road(pair[0], pair[1], half_width);
+            }
+            junctions.insert(road.from);
+            junctions.insert(road.to);
+        }
+    }
+    for node in junctions {
+        if let Some(junction) = graph.junction(node) {
+            road_areas.add_junction(junction.clone());
+        }
+    }
+
+    for (entity, controller) in signals.iter() {
+        let pos = match graph.pos(entity) {
+            Some(pos) => pos,
+            None => continue,
+        };
+        let phase = controller.phase();
+        for (index, approach) in controller.approaches.iter().enumerate() {
+            road_areas.add_crosswalk(Crosswalk {
+                center: pos + (approach.setback + config.crosswalk_width / 2.0) * approach.dir,
+                across: approach.dir.perp(),
+                half_length: approach.half_width,
+                half_width: config.crosswalk_width / 2.0,
+                walk: phase == SignalPhase::Walk,
+            });
+            if approach.incoming && phase != SignalPhase::Green(index) {
+                stop_lines.push(StopLine {
+                    pos: pos + (approach.setback + config.crosswalk_width) * approach.dir,
+                    dir: -approach.dir,
+                    half_width: approach.half_width,
+                });
+            }
+        }
+    }
+}
+
+/// Kerbs along a street or around a junction, as the polylines of their colliders.
+#[derive(Component, Debug, PartialEq)]
+pub struct Kerbs(Vec<Vec<Vec2>>);
+
+/// Collider of one of the kerbs of a street or a junction, as a child of the road or the node.
+#[derive(Component)]
+pub struct Kerb;
+
+/// Kerbs along both edges of the carriageway of a street, from the edge of the junctions at its
+/// ends, leaving room for their crosswalks.
+fn kerb_lines(graph: &RoadGraph, config: &SignalConfig, road: &Road) -> Vec<Vec<Vec2>> {
+    let points = match graph.centerline(road.from, road.to) {
+        Some(points) => points,
+        None => return vec![],
+    };
+    // Dead ends have no crosswalk.
+    let crosswalk_width = |node| match graph.junction(node) {
+        Some(junction) if junction.streets.len() >= 2 => config.crosswalk_width,
+        _ => 0.0,
+    };
+    let start = graph.junction_setback(road.from, road.to) + crosswalk_width(road.from);
+    let end = curve::length(points)
+        - graph.junction_setback(road.to, road.from)
+        - crosswalk_width(road.to);
+    if end <= start {
+        return vec![];
+    }
+    let section = curve::section(points, start, end);
+    [-1.0, 1.0]
+        .into_iter()
+        .map(|side| curve::offset(&section, side * road.width / 2.0))
+        .collect()
+}
+
+/// Keeps the kerb colliders of the streets, and around the corners of their junctions, in line
+/// with their nodes and widths.
+pub fn update_kerbs(
+    mut commands: Commands,
+    config: Res<SignalConfig>,
+    graph: Res<RoadGraph>,
+    roads: Query<(Entity, &Road, Option<&Kerbs>, Option<&Children>)>,
+    nodes: Query<(Entity, &RoadNode, Option<&Kerbs>, Option<&Children>)>,
+    kerbs: Query<(), With<Kerb>>,
+) {
+    for (road_entity, road, current, children) in roads.iter() {
+        let lines = if config.enabled && road.is_street() {
+            kerb_lines(&graph, &config, road)
+        } else {
+            vec![]
+        };
+        replace_kerbs(&mut commands, road_entity, current, children, &kerbs, lines);
+    }
+    for (node_entity, node, current, children) in nodes.iter() {
+        // Relative to the node, as its children.
+        let lines = match graph.junction(node_entity) {
+            Some(junction) if config.enabled => junction
+                .corners
+                .iter()
+                .map(|corner| corner.iter().map(|&point| point - node.pos).collect())
+                .collect(),
+            _ => vec![],
+        };
+        replace_kerbs(&mut commands, node_entity, current, children, &kerbs, lines);
+    }
+}
+
+fn replace_kerbs(
+    commands: &mut Commands,
+    entity: Entity,
+    current: Option<&Kerbs>,
+    children: Option<&Children>,
+    kerbs: &Query<(), With<Kerb>>,
+    lines: Vec<Vec<Vec2>>,
+) {
+    if current.map_or(lines.is_empty(), |current| current.0 == lines) {
+        return;
+    }
+    for &child in children.into_iter().flat_map(|children| children.iter()) {
+        if kerbs.get(child).is_ok() {
+            commands.entity(child).despawn_recursive();
+        }
+    }
+    for line in &lines {
+        let collider = Collider::polyline(line.clone(), None);
+        commands.entity(entity).add_children(|children| {
+            children
+                .spawn()
+                .insert(Kerb)
+                .insert(RigidBody::Fixed)
+                .insert(collider)
+                .insert_bundle(TransformBundle::default());
+        });
+    }
+    commands.entity(entity).insert(Kerbs(lines));
+}
+
+fn color_crosswalks(
+    signals: Query<&SignalController>,
+    mut markings: Query<(&mut CrosswalkMarking, &mut DrawMode, &Parent)>,
+) {
+    for (mut marking, mut draw_mode, parent) in markings.iter_mut() {
+        let walk = signals
+            .get(parent.get())
+            .is_ok_and(|controller| controller.phase() == SignalPhase::Walk);
+        if marking.walk != walk {
+            marking.walk = walk;
+            let color = if walk { WALK_COLOR } else { DONT_WALK_COLOR };
+            *draw_mode = DrawMode::Fill(FillMode::color(color));
+        }
+    }
+}
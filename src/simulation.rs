@@ -0,0 +1,71 @@
+use crate::{ai::CrowdAiLabel, metrics::*, person::PersonLabel, rng::SimRng};
+use bevy::{prelude::*, utils::Duration};
+use bevy_rapier2d::prelude::*;
+
+pub const SIM_TIMESTEP: f32 = 1.0 / 60.0;
+
+/// Physics, simulated time, random numbers and metrics shared by the other plugins.
+pub struct SimulationPlugin {
+    pub seed: u64,
+}
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let seed = self.seed;
+        crate::add_event_once::<crate::ai::Arrived>(app);
+        crate::add_event_once::<crate::ai::TripFailed>(app);
+        crate::add_event_once::<crate::ai::stuck::Stuck>(app);
+        crate::add_event_once::<crate::ai::stuck::GaveUp>(app);
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(3.0))
+            .insert_resource(rapier_configuration())
+            .init_resource::<SimClock>()
+            .insert_resource(SimRng::seeded(seed))
+            .init_resource::<SimMetrics>()
+            .add_startup_system(move || info!("Simulation seed: {}", seed))
+            .add_system_to_stage(CoreStage::First, tick)
+            .add_system(record_trips.after(CrowdAiLabel::PersonActions))
+            .add_system(record_collisions)
+            .add_system(record_stuck.after(CrowdAiLabel::TrackProgress))
+            .add_system(record_fundamental_diagram.after(PersonLabel::Density));
+    }
+}
+
+/// Simulated time. Every frame advances the simulation by exactly one `SIM_TIMESTEP`, in lockstep
+/// with the physics, so a run never depends on the frame rate.
+#[derive(Default)]
+pub struct SimClock {
+    pub tick: u64,
+}
+
+impl SimClock {
+    pub fn delta(&self) -> Duration {
+        Duration::from_secs_f32(SIM_TIMESTEP)
+    }
+
+    pub fn elapsed_secs(&self) -> f32 {
+        self.tick as f32 * SIM_TIMESTEP
+    }
+}
+
+pub fn tick(mut clock: ResMut<SimClock>) {
+    clock.tick += 1;
+}
+
+pub fn rapier_configuration() -> RapierConfiguration {
+    RapierConfiguration {
+        gravity: Vec2::ZERO,
+        timestep_mode: TimestepMode::Fixed {
+            dt: SIM_TIMESTEP,
+            substeps: 1,
+        },
+        ..default()
+    }
+}
+
+/// Reads the simulation seed from `--seed <n>` or the `SIM_SEED` environment variable.
+pub fn seed_from_args() -> Option<u64> {
+    let mut args = std::env::args().skip_while(|arg| arg != "--seed").skip(1);
+    args.next()
+        .or_else(|| std::env::var("SIM_SEED").ok())
+        .and_then(|seed| seed.parse().ok())
+}
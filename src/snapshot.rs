@@ -0,0 +1,441 @@
+use crate::{
+    ai::{
+        group::{self, Group},
+        Actions, BuildPath, Target, TargetDoor,
+    },
+    building::Building,
+    config::{CrowdConfig, TransitConfig, VehicleConfig},
+    level::*,
+    person::{self, Person, PersonState, Walker},
+    player::Player,
+    rng::SimRng,
+    road::*,
+    signal::{SignalController, SignalTiming},
+    simulation::SimClock,
+    spawning::PersonSpawnTimer,
+    transit::TransitLine,
+    vehicle::{
+        self,
+        bus::{self, Bus, Passenger},
+        route::LanePath,
+        Vehicle,
+    },
+};
+use bevy::{
+    math::Vec3Swizzles,
+    prelude::*,
+    utils::{Duration, HashMap},
+};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub const SNAPSHOT_PATH: &str = "snapshot.ron";
+
+const SAVE_SNAPSHOT: KeyCode = KeyCode::F5;
+const LOAD_SNAPSHOT: KeyCode = KeyCode::F9;
+
+/// Saves the simulation with F5 and restores it with F9.
+pub struct SnapshotPlugin;
+
+impl Plugin for SnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CrowdConfig>()
+            .init_resource::<VehicleConfig>()
+            .init_resource::<TransitConfig>()
+            .add_system(save_snapshot)
+            .add_system_to_stage(CoreStage::PostUpdate, load_snapshot);
+    }
+}
+
+/// Full simulation state. Contacts inside the physics engine are not part of it, so a restored
+/// simulation settles its collisions again on the first step.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub level: Level,
+    pub people: Vec<PersonSnapshot>,
+    #[serde(default)]
+    pub groups: Vec<GroupSnapshot>,
+    #[serde(default)]
+    pub vehicles: Vec<VehicleSnapshot>,
+    #[serde(default)]
+    pub buses: Vec<BusSnapshot>,
+    #[serde(default)]
+    pub signals: Vec<SignalSnapshot>,
+    pub tick: u64,
+    pub spawn_timer_elapsed: f32,
+    pub rng: SimRng,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PersonSnapshot {
+    pub pos: Vec2,
+    pub linvel: Vec2,
+    pub state: PersonState,
+    pub walker: Option<Walker>,
+    pub target: Option<Vec2>,
+    pub target_door: Option<Vec2>,
+    pub actions: Option<Actions>,
+    pub build_path: bool,
+    pub player: bool,
+}
+
+/// Shared plan of a group, whose members are indices into the people of the snapshot.
+#[derive(Serialize, Deserialize)]
+pub struct GroupSnapshot {
+    pub members: Vec<usize>,
+    pub target: Vec2,
+    pub target_door: Option<Vec2>,
+    pub actions: Option<Actions>,
+    pub build_path: bool,
+}
+
+/// A vehicle, whose destination is an index into the nodes of the level.
+#[derive(Serialize, Deserialize)]
+pub struct VehicleSnapshot {
+    pub speed: f32,
+    pub destination: usize,
+    pub lane: u32,
+    pub path: LanePath,
+    pub distance: f32,
+}
+
+impl VehicleSnapshot {
+    fn new(vehicle: &Vehicle, node_indices: &HashMap<Entity, usize>) -> Option<Self> {
+        Some(Self {
+            speed: vehicle.speed,
+            destination: *node_indices.get(&vehicle.destination)?,
+            lane: vehicle.lane,
+            path: vehicle.path.clone(),
+            distance: vehicle.distance,
+        })
+    }
+
+    fn into_vehicle(self, nodes: &[Entity]) -> Option<Vehicle> {
+        Some(Vehicle {
+            speed: self.speed,
+            destination: *nodes.get(self.destination)?,
+            lane: self.lane,
+            path: self.path,
+            distance: self.distance,
+        })
+    }
+}
+
+/// A bus with the people riding it, whose line is an index into the transit lines of the level.
+#[derive(Serialize, Deserialize)]
+pub struct BusSnapshot {
+    pub vehicle: VehicleSnapshot,
+    pub line: usize,
+    pub stops: Vec<(Vec2, f32)>,
+    pub next_stop: usize,
+    pub dwell: Option<f32>,
+    pub finished: bool,
+    pub passengers: Vec<Passenger>,
+}
+
+/// The phase of the signal controller on the node at this index of the level.
+#[derive(Serialize, Deserialize)]
+pub struct SignalSnapshot {
+    pub node: usize,
+    pub timing: SignalTiming,
+}
+
+type PersonQuery<'a> = (
+    Entity,
+    &'a Transform,
+    &'a Velocity,
+    &'a Person,
+    Option<&'a Walker>,
+    Option<&'a Target>,
+    Option<&'a TargetDoor>,
+    Option<&'a Actions>,
+    Option<&'a BuildPath>,
+    Option<&'a Player>,
+);
+
+type GroupQuery<'a> = (
+    &'a Group,
+    &'a Target,
+    Option<&'a TargetDoor>,
+    Option<&'a Actions>,
+    Option<&'a BuildPath>,
+);
+
+#[allow(clippy::too_many_arguments)]
+pub fn save_snapshot(
+    keyboard: Res<Input<KeyCode>>,
+    people: Query<PersonQuery>,
+    groups: Query<GroupQuery>,
+    nodes: Query<(Entity, &RoadNode)>,
+    roads: Query<&Road>,
+    buildings: Query<&Building>,
+    lines: Query<(Entity, &TransitLine)>,
+    vehicles: Query<&Vehicle, Without<Bus>>,
+    buses: Query<(&Vehicle, &Bus)>,
+    signals: Query<&SignalController>,
+    clock: Res<SimClock>,
+    timer: Res<PersonSpawnTimer>,
+    rng: Res<SimRng>,
+) {
+    if !keyboard.just_pressed(SAVE_SNAPSHOT) {
+        return;
+    }
+    let indices: HashMap<Entity, usize> = people
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, ..))| (entity, index))
+        .collect();
+    // In the order `Level::from_world` saves them.
+    let node_indices: HashMap<Entity, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, _))| (entity, index))
+        .collect();
+    // Only the lines between saved nodes, which are all of them unless a node was just removed.
+    let saved_lines: Vec<(Entity, &TransitLine)> = lines
+        .iter()
+        .filter(|(_, line)| {
+            line.nodes
+                .iter()
+                .all(|node| node_indices.contains_key(node))
+        })
+        .collect();
+    let line_indices: HashMap<Entity, usize> = saved_lines
+        .iter()
+        .enumerate()
+        .map(|(index, &(entity, _))| (entity, index))
+        .collect();
+    let snapshot = Snapshot {
+        level: Level::from_world(
+            nodes.iter(),
+            roads.iter(),
+            buildings.iter(),
+            saved_lines.iter().map(|&(_, line)| line),
+        ),
+        people: people
+            .iter()
+            .map(
+                |(
+                    _,
+                    transform,
+                    velocity,
+                    person,
+                    walker,
+                    target,
+                    target_door,
+                    actions,
+                    build_path,
+                    player,
+                )| {
+                    PersonSnapshot {
+                        pos: transform.translation.xy(),
+                        linvel: velocity.linvel,
+                        state: person.state.clone(),
+                        walker: walker.copied(),
+                        target: target.map(|target| **target),
+                        target_door: target_door.map(|target_door| **target_door),
+                        actions: actions.cloned(),
+                        build_path: build_path.is_some(),
+                        player: player.is_some(),
+                    }
+                },
+            )
+            .collect(),
+        groups: groups
+            .iter()
+            .map(
+                |(group, target, target_door, actions, build_path)| GroupSnapshot {
+                    members: group
+                        .members
+                        .iter()
+                        .filter_map(|member| indices.get(member).copied())
+                        .collect(),
+                    target: **target,
+                    target_door: target_door.map(|target_door| **target_door),
+                    actions: actions.cloned(),
+                    build_path: build_path.is_some(),
+                },
+            )
+            .collect(),
+        vehicles: vehicles
+            .iter()
+            .filter_map(|vehicle| VehicleSnapshot::new(vehicle, &node_indices))
+            .collect(),
+        buses: buses
+            .iter()
+            .filter_map(|(vehicle, bus)| {
+                Some(BusSnapshot {
+                    vehicle: VehicleSnapshot::new(vehicle, &node_indices)?,
+                    line: *line_indices.get(&bus.line)?,
+                    stops: bus.stops.clone(),
+                    next_stop: bus.next_stop,
+                    dwell: bus.dwell,
+                    finished: bus.finished,
+                    passengers: bus.passengers.clone(),
+                })
+            })
+            .collect(),
+        signals: nodes
+            .iter()
+            .filter_map(|(entity, _)| {
+                Some(SignalSnapshot {
+                    node: node_indices[&entity],
+                    timing: signals.get(entity).ok()?.timing(),
+                })
+            })
+            .collect(),
+        tick: clock.tick,
+        spawn_timer_elapsed: timer.elapsed_secs(),
+        rng: (*rng).clone(),
+    };
+    match write_ron(&snapshot, SNAPSHOT_PATH) {
+        Ok(()) => info!("Saved snapshot to {}", SNAPSHOT_PATH),
+        Err(err) => error!("Could not save snapshot to {}: {}", SNAPSHOT_PATH, err),
+    }
+}
+
+/// Everything a snapshot saves, to be replaced when loading one.
+type SavedFilter = Or<(
+    With<Person>,
+    With<Group>,
+    With<Vehicle>,
+    With<Road>,
+    With<RoadNode>,
+    With<Building>,
+    With<TransitLine>,
+)>;
+
+/// Runs in `PostUpdate`, so the despawned map is not reported as removed to the path
+/// invalidation systems and the restored plans are kept.
+#[allow(clippy::too_many_arguments)]
+pub fn load_snapshot(
+    mut commands: Commands,
+    keyboard: Res<Input<KeyCode>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    crowd_config: Res<CrowdConfig>,
+    vehicle_config: Res<VehicleConfig>,
+    transit_config: Res<TransitConfig>,
+    mut clock: ResMut<SimClock>,
+    mut timer: ResMut<PersonSpawnTimer>,
+    mut rng: ResMut<SimRng>,
+    to_despawn: Query<Entity, SavedFilter>,
+) {
+    if !keyboard.just_pressed(LOAD_SNAPSHOT) {
+        return;
+    }
+    let snapshot: Snapshot = match read_ron(SNAPSHOT_PATH)
+        .and_then(|snapshot: Snapshot| snapshot.level.validate().map(|_| snapshot))
+    {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            error!("Could not load snapshot from {}: {}", SNAPSHOT_PATH, err);
+            return;
+        }
+    };
+
+    for entity in to_despawn.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let (nodes, lines) = snapshot.level.spawn(&mut commands);
+    for signal in &snapshot.signals {
+        if let Some(&node) = nodes.get(signal.node) {
+            commands.entity(node).insert(signal.timing);
+        }
+    }
+    for vehicle_snapshot in snapshot.vehicles {
+        if let Some(vehicle) = vehicle_snapshot.into_vehicle(&nodes) {
+            vehicle::add_vehicle(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &vehicle_config,
+                vehicle,
+            );
+        }
+    }
+    for bus_snapshot in snapshot.buses {
+        let (vehicle, &line) = match (
+            bus_snapshot.vehicle.into_vehicle(&nodes),
+            lines.get(bus_snapshot.line),
+        ) {
+            (Some(vehicle), Some(line)) => (vehicle, line),
+            _ => continue,
+        };
+        bus::add_bus(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &transit_config,
+            vehicle,
+            Bus {
+                line,
+                stops: bus_snapshot.stops,
+                next_stop: bus_snapshot.next_stop,
+                dwell: bus_snapshot.dwell,
+                finished: bus_snapshot.finished,
+                passengers: bus_snapshot.passengers,
+            },
+        );
+    }
+    let mut people = Vec::with_capacity(snapshot.people.len());
+    for person_snapshot in snapshot.people {
+        let person_entity = person::add_person(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &crowd_config,
+            person_snapshot.pos,
+        );
+        let mut person_commands = commands.entity(person_entity);
+        person_commands
+            .insert(Person {
+                state: person_snapshot.state,
+            })
+            .insert(Velocity::linear(person_snapshot.linvel));
+        if let Some(walker) = person_snapshot.walker {
+            person_commands.insert(walker);
+        }
+        if let Some(target) = person_snapshot.target {
+            person_commands.insert(Target(target));
+        }
+        if let Some(target_door) = person_snapshot.target_door {
+            person_commands.insert(TargetDoor(target_door));
+        }
+        if let Some(actions) = person_snapshot.actions {
+            person_commands.insert(actions);
+        }
+        if person_snapshot.build_path {
+            person_commands.insert(BuildPath);
+        }
+        if person_snapshot.player {
+            person_commands.insert(Player);
+        }
+        people.push(person_entity);
+    }
+    for group_snapshot in snapshot.groups {
+        let members: Vec<Entity> = group_snapshot
+            .members
+            .iter()
+            .map(|&index| people[index])
+            .collect();
+        let group_entity = group::add_group(&mut commands, members, group_snapshot.target);
+        let mut group_commands = commands.entity(group_entity);
+        group_commands.insert(Target(group_snapshot.target));
+        if let Some(target_door) = group_snapshot.target_door {
+            group_commands.insert(TargetDoor(target_door));
+        }
+        if let Some(actions) = group_snapshot.actions {
+            group_commands.insert(actions);
+        }
+        if group_snapshot.build_path {
+            group_commands.insert(BuildPath);
+        }
+    }
+
+    clock.tick = snapshot.tick;
+    timer.set_elapsed(Duration::from_secs_f32(snapshot.spawn_timer_elapsed));
+    *rng = snapshot.rng;
+    info!("Loaded snapshot from {}", SNAPSHOT_PATH);
+}
@@ -0,0 +1,190 @@
+use crate::person::Person;
+use bevy::{math::Vec3Swizzles, prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+
+/// Side of the square cells people are sorted into.
+pub const DEFAULT_CELL_SIZE: f32 = 5.0;
+
+/// Positions and velocities of all people sorted into a uniform grid, rebuilt every frame so
+/// movement can find neighbours without querying the physics engine.
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    entries: Vec<SpatialEntry>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SpatialEntry {
+    pub entity: Entity,
+    pub pos: Vec2,
+    pub vel: Vec2,
+}
+
+impl Default for SpatialHash {
+    fn default() -> Self {
+        Self::new(DEFAULT_CELL_SIZE)
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::default(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Removes all entries, keeping the allocated cells for the next rebuild.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, pos: Vec2, vel: Vec2) {
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_default().push(self.entries.len());
+        self.entries.push(SpatialEntry { entity, pos, vel });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entries within `radius` of `pos`, in no particular order.
+    pub fn within_radius(
+        &self,
+        pos: Vec2,
+        radius: f32,
+    ) -> impl Iterator<Item = &SpatialEntry> + '_ {
+        let (min_x, min_y) = self.cell(pos - radius);
+        let (max_x, max_y) = self.cell(pos + radius);
+        (min_x..=max_x)
+            .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .map(|&index| &self.entries[index])
+            .filter(move |entry| entry.pos.distance_squared(pos) <= radius * radius)
+    }
+
+    /// Returns the `k` entries closest to `pos` within `max_distance`, closest first, leaving out
+    /// `except`.
+    pub fn k_nearest(
+        &self,
+        pos: Vec2,
+        k: usize,
+        max_distance: f32,
+        except: Option<Entity>,
+    ) -> Vec<&SpatialEntry> {
+        let mut nearest: Vec<_> = self
+            .within_radius(pos, max_distance)
+            .filter(|entry| Some(entry.entity) != except)
+            .collect();
+        nearest.sort_by(|a, b| {
+            a.pos
+                .distance_squared(pos)
+                .total_cmp(&b.pos.distance_squared(pos))
+        });
+        nearest.truncate(k);
+        nearest
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        let cell = (pos / self.cell_size).floor();
+        (cell.x as i32, cell.y as i32)
+    }
+}
+
+pub fn update_spatial_hash(
+    mut spatial_hash: ResMut<SpatialHash>,
+    people: Query<(Entity, &Transform, &Velocity), With<Person>>,
+) {
+    spatial_hash.clear();
+    for (entity, transform, velocity) in people.iter() {
+        spatial_hash.insert(entity, transform.translation.xy(), velocity.linvel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn random_hash(rng: &mut ChaCha8Rng, count: u32) -> (SpatialHash, Vec<SpatialEntry>) {
+        let mut hash = SpatialHash::default();
+        let mut entries = vec![];
+        for index in 0..count {
+            let entity = Entity::from_raw(index);
+            let pos = Vec2::new(rng.gen_range(-50.0..50.0), rng.gen_range(-50.0..50.0));
+            hash.insert(entity, pos, Vec2::ZERO);
+            entries.push(SpatialEntry {
+                entity,
+                pos,
+                vel: Vec2::ZERO,
+            });
+        }
+        (hash, entries)
+    }
+
+    #[test]
+    fn queries_match_brute_force() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let (hash, entries) = random_hash(&mut rng, 500);
+        for _ in 0..100 {
+            let pos = Vec2::new(rng.gen_range(-60.0..60.0), rng.gen_range(-60.0..60.0));
+            let radius = rng.gen_range(0.0..20.0);
+
+            let mut found: Vec<u32> = hash
+                .within_radius(pos, radius)
+                .map(|entry| entry.entity.id())
+                .collect();
+            found.sort_unstable();
+            let expected: Vec<u32> = entries
+                .iter()
+                .filter(|entry| entry.pos.distance_squared(pos) <= radius * radius)
+                .map(|entry| entry.entity.id())
+                .collect();
+            assert_eq!(found, expected);
+
+            let except = Some(Entity::from_raw(rng.gen_range(0..500)));
+            let k = rng.gen_range(1..12);
+            let nearest: Vec<u32> = hash
+                .k_nearest(pos, k, radius, except)
+                .iter()
+                .map(|entry| entry.entity.id())
+                .collect();
+            let mut expected: Vec<&SpatialEntry> = entries
+                .iter()
+                .filter(|entry| Some(entry.entity) != except)
+                .filter(|entry| entry.pos.distance_squared(pos) <= radius * radius)
+                .collect();
+            expected.sort_by(|a, b| {
+                a.pos
+                    .distance_squared(pos)
+                    .total_cmp(&b.pos.distance_squared(pos))
+            });
+            let expected: Vec<u32> = expected
+                .iter()
+                .take(k)
+                .map(|entry| entry.entity.id())
+                .collect();
+            assert_eq!(nearest, expected);
+        }
+    }
+
+    #[test]
+    fn cleared_hash_is_empty() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let (mut hash, _) = random_hash(&mut rng, 50);
+        hash.clear();
+        assert!(hash.is_empty());
+        assert_eq!(hash.within_radius(Vec2::ZERO, 100.0).count(), 0);
+    }
+}
@@ -1,41 +1,231 @@
 use crate::{
-    ai::{BuildPath, Target},
+    ai::{group, BuildPath, CrowdAiLabel, Target, TargetDoor},
     building::Door,
+    config::{CrowdConfig, GroupConfig, SpawnConfig, WalkerConfig},
+    metrics::TripStart,
+    od_matrix::{OdEndpoint, OdMatrix},
     person,
+    rng::SimRng,
+    signal::RoadAreas,
+    simulation::SimClock,
 };
 use bevy::{math::Vec3Swizzles, prelude::*, utils::Duration};
-use rand::seq::IteratorRandom;
+use bevy_rapier2d::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+use rand_distr::{Distribution, Poisson};
+use std::f32::consts::TAU;
+
+/// Random spots of a zone without doors tried before giving up on a trip to or from it, when
+/// roads and buildings cover most of it.
+const ZONE_SPOT_ATTEMPTS: usize = 20;
+
+/// Spawns people, alone or in groups, at a door, heading to another door: periodically between
+/// random doors, or as the trips of the origin-destination matrix when one is loaded.
+pub struct SpawningPlugin;
+
+#[derive(SystemLabel)]
+pub enum SpawningLabel {
+    Spawn,
+}
+
+impl Plugin for SpawningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpawnConfig>()
+            .init_resource::<CrowdConfig>()
+            .init_resource::<WalkerConfig>()
+            .init_resource::<GroupConfig>()
+            .init_resource::<OdMatrix>()
+            .init_resource::<RoadAreas>()
+            .add_startup_system(setup)
+            .add_system(
+                spawn_person
+                    .label(SpawningLabel::Spawn)
+                    .before(CrowdAiLabel::PathUpdate),
+            );
+    }
+}
 
 #[derive(Component, Deref, DerefMut)]
 pub struct PersonSpawnTimer(Timer);
 
-pub fn setup(mut commands: Commands) {
+pub fn setup(mut commands: Commands, config: Res<SpawnConfig>) {
     commands.insert_resource(PersonSpawnTimer(Timer::new(
-        Duration::from_secs_f32(1.0),
+        Duration::from_secs_f32(config.interval),
         true,
-    )))
+    )));
+    if let Some(path) = &config.od_matrix {
+        let od_matrix = OdMatrix::load(path, &config.zones)
+            .unwrap_or_else(|err| panic!("Could not load OD matrix {}: {}", path, err));
+        commands.insert_resource(od_matrix);
+    }
+}
+
+/// One end of a trip: a door, with the direction it opens in, or a spot of a zone without doors.
+#[derive(Clone, Copy, PartialEq)]
+struct TripEnd {
+    pos: Vec2,
+    open_dir: Option<Vec2>,
 }
 
+impl TripEnd {
+    /// Where people appear at or head to.
+    fn spot(&self, door_distance: f32) -> Vec2 {
+        self.pos + door_distance * self.open_dir.unwrap_or_default()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn spawn_person(
     mut commands: Commands,
+    config: Res<SpawnConfig>,
+    crowd_config: Res<CrowdConfig>,
+    walker_config: Res<WalkerConfig>,
+    group_config: Res<GroupConfig>,
+    od_matrix: Res<OdMatrix>,
     mut timer: ResMut<PersonSpawnTimer>,
-    time: Res<Time>,
+    mut rng: ResMut<SimRng>,
+    clock: Res<SimClock>,
+    rapier_ctx: Res<RapierContext>,
+    road_areas: Res<RoadAreas>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     doors: Query<(&GlobalTransform, &Door)>,
 ) {
-    timer.tick(time.delta());
-    if timer.just_finished() {
-        let mut rng = rand::thread_rng();
-        let (from_transform, from_door) = doors.iter().choose(&mut rng).unwrap();
-        let (to_transform, to_door) = doors.iter().choose(&mut rng).unwrap();
-        let spawn_pos = from_transform.translation().xy() + (2.0 * from_door.get_open_dir());
-        let target_pos = to_transform.translation().xy() + (2.0 * to_door.get_open_dir());
-        let person_entity =
-            person::add_person(&mut commands, &mut meshes, &mut materials, spawn_pos);
-        commands
-            .entity(person_entity)
-            .insert(Target(target_pos))
+    if !config.enabled {
+        return;
+    }
+    let doors: Vec<TripEnd> = doors
+        .iter()
+        .map(|(transform, door)| TripEnd {
+            pos: transform.translation().xy(),
+            open_dir: Some(door.get_open_dir()),
+        })
+        .collect();
+    let mut trips = vec![];
+    if od_matrix.is_empty() {
+        timer.tick(clock.delta());
+        if timer.just_finished() {
+            // Levels with fewer than two doors, as the editor can make, spawn nobody.
+            if let [from, to] = doors.choose_multiple(&mut **rng, 2).collect::<Vec<_>>()[..] {
+                trips.push((*from, *to));
+            }
+        }
+    } else {
+        let (time, dt) = (clock.elapsed_secs(), clock.delta().as_secs_f32());
+        // Off the carriageway and clear of buildings, for someone to stand there.
+        let walkable = |pos: Vec2| {
+            road_areas.walkable(pos)
+                && rapier_ctx
+                    .intersection_with_shape(
+                        pos,
+                        0.0,
+                        &Collider::ball(crowd_config.half_size),
+                        QueryFilter::only_fixed(),
+                    )
+                    .is_none()
+        };
+        for flow in &od_matrix.flows {
+            let expected = flow.rate(time) * dt;
+            if expected <= 0.0 {
+                continue;
+            }
+            let count = Poisson::new(expected).map_or(0.0, |poisson| poisson.sample(&mut **rng));
+            for _ in 0..count as usize {
+                let from = match trip_end(&flow.origin, &doors, None, walkable, &mut **rng) {
+                    Some(from) => from,
+                    None => continue,
+                };
+                let to = trip_end(&flow.destination, &doors, Some(from), walkable, &mut **rng);
+                if let Some(to) = to {
+                    trips.push((from, to));
+                }
+            }
+        }
+    }
+
+    for (from, to) in trips {
+        let spawn_pos = from.spot(config.door_distance);
+        let size = if group_config.max_size >= 2 && rng.gen::<f32>() < group_config.probability {
+            rng.gen_range(2..=group_config.max_size)
+        } else {
+            1
+        };
+        // Members of a group appear side by side in front of the door.
+        let across = from.open_dir.unwrap_or(Vec2::Y).perp();
+        let mut people = Vec::with_capacity(size);
+        for index in 0..size {
+            let offset = (index as f32 - (size as f32 - 1.0) / 2.0) * group_config.spacing;
+            let walker = walker_config.sample(&mut **rng);
+            let person_entity = person::add_person(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &crowd_config,
+                spawn_pos + offset * across,
+            );
+            commands
+                .entity(person_entity)
+                .insert(walker)
+                .insert(TripStart(clock.elapsed_secs()));
+            people.push(person_entity);
+        }
+
+        // A group plans for all its members.
+        let planner = if size == 1 {
+            people[0]
+        } else {
+            group::add_group(&mut commands, people, spawn_pos)
+        };
+        let mut planner = commands.entity(planner);
+        planner
+            .insert(Target(to.spot(config.door_distance)))
             .insert(BuildPath);
+        if to.open_dir.is_some() {
+            planner.insert(TargetDoor(to.pos));
+        }
     }
 }
+
+/// Resolves an end of a trip of the matrix to a door other than `other`, or to a random walkable
+/// spot of a zone without doors. Trips that can not go between two different places are dropped.
+fn trip_end(
+    endpoint: &OdEndpoint,
+    doors: &[TripEnd],
+    other: Option<TripEnd>,
+    walkable: impl Fn(Vec2) -> bool,
+    rng: &mut impl Rng,
+) -> Option<TripEnd> {
+    let end = match endpoint {
+        OdEndpoint::Door(pos) => *doors
+            .iter()
+            .min_by(|a, b| a.pos.distance(*pos).total_cmp(&b.pos.distance(*pos)))?,
+        OdEndpoint::Zone(zone) => {
+            let inside: Vec<TripEnd> = doors
+                .iter()
+                .copied()
+                .filter(|door| zone.contains(door.pos))
+                .collect();
+            if inside.is_empty() {
+                // Anywhere walkable within the zone, uniformly.
+                let pos = (0..ZONE_SPOT_ATTEMPTS)
+                    .map(|_| {
+                        let distance = zone.radius * rng.gen::<f32>().sqrt();
+                        let angle = rng.gen_range(0.0..TAU);
+                        zone.pos + distance * Vec2::new(angle.cos(), angle.sin())
+                    })
+                    .find(|&pos| walkable(pos))?;
+                TripEnd {
+                    pos,
+                    open_dir: None,
+                }
+            } else {
+                let candidates: Vec<TripEnd> = inside
+                    .into_iter()
+                    .filter(|&door| Some(door) != other)
+                    .collect();
+                *candidates.choose(rng)?
+            }
+        }
+    };
+    (Some(end) != other).then_some(end)
+}
@@ -0,0 +1,137 @@
+//! Public transit: a bus leaves the first node of every transit line at its headway, drives along
+//! the line with the other vehicles and stands at each of its stops for people to get on and off,
+//! until it reaches the last node. People ride when it gets them to their door sooner than
+//! walking, see `ai::transit`.
+
+use crate::{
+    ai::{
+        transit::{board_and_alight, give_up_waiting},
+        CrowdAiLabel,
+    },
+    city::CityLabel,
+    config::{CrowdConfig, TransitConfig, VehicleConfig},
+    road::RoadGraph,
+    simulation::SimClock,
+    vehicle::{
+        bus::{serve_stops, spawn_bus},
+        Vehicle, VehicleLabel,
+    },
+};
+use bevy::{math::Vec3Swizzles, prelude::*};
+use bevy_prototype_lyon::prelude::{FillMode, *};
+
+const STOP_RADIUS: f32 = 1.5;
+const STOP_COLOR: Color = Color::ORANGE;
+
+pub struct TransitPlugin;
+
+#[derive(SystemLabel)]
+pub enum TransitLabel {
+    Stops,
+}
+
+impl Plugin for TransitPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TransitConfig>()
+            .init_resource::<VehicleConfig>()
+            .init_resource::<CrowdConfig>()
+            .init_resource::<RoadGraph>()
+            .add_system(draw_stops)
+            .add_system(run_lines.after(CityLabel::RoadGraph))
+            .add_system(
+                serve_stops
+                    .label(TransitLabel::Stops)
+                    .after(VehicleLabel::Drive),
+            )
+            .add_system(
+                board_and_alight
+                    .after(TransitLabel::Stops)
+                    .before(CrowdAiLabel::PathUpdate),
+            )
+            .add_system(give_up_waiting.before(CrowdAiLabel::PathUpdate));
+    }
+}
+
+/// A bus line along the streets between its nodes, in order, stopping at its stops, in order
+/// along the line too. Stops are on the sidewalk on the right of the line, where people wait.
+#[derive(Component, Clone, Debug)]
+pub struct TransitLine {
+    pub nodes: Vec<Entity>,
+    pub stops: Vec<Vec2>,
+    /// Seconds between two buses.
+    pub headway: f32,
+}
+
+/// Seconds until the next bus leaves the start of the line.
+#[derive(Component, Deref, DerefMut)]
+pub struct NextDeparture(f32);
+
+/// Sends a bus along every line at its headway, as soon as there is room for it at the start.
+#[allow(clippy::too_many_arguments)]
+pub fn run_lines(
+    mut commands: Commands,
+    config: Res<TransitConfig>,
+    clock: Res<SimClock>,
+    graph: Res<RoadGraph>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut lines: Query<(Entity, &TransitLine, Option<&mut NextDeparture>)>,
+    vehicles: Query<&Transform, With<Vehicle>>,
+) {
+    if !config.enabled {
+        return;
+    }
+    let dt = clock.delta().as_secs_f32();
+    for (line_entity, line, next_departure) in lines.iter_mut() {
+        let mut next_departure = match next_departure {
+            Some(next_departure) => next_departure,
+            None => {
+                commands.entity(line_entity).insert(NextDeparture(0.0));
+                continue;
+            }
+        };
+        **next_departure -= dt;
+        if **next_departure > 0.0 {
+            continue;
+        }
+        let start = match line.nodes.first().and_then(|&node| graph.pos(node)) {
+            Some(start) => start,
+            None => continue,
+        };
+        if vehicles
+            .iter()
+            .any(|transform| transform.translation.xy().distance(start) < config.bus_length)
+        {
+            continue;
+        }
+        if spawn_bus(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &config,
+            &graph,
+            line_entity,
+            line,
+        )
+        .is_some()
+        {
+            **next_departure += line.headway;
+        }
+    }
+}
+
+fn draw_stops(mut commands: Commands, lines: Query<(Entity, &TransitLine), Added<TransitLine>>) {
+    for (line_entity, line) in lines.iter() {
+        let mut gb = GeometryBuilder::new();
+        for &stop in &line.stops {
+            gb = gb.add(&shapes::Circle {
+                radius: STOP_RADIUS,
+                center: stop,
+            });
+        }
+        commands.entity(line_entity).insert_bundle(gb.build(
+            DrawMode::Fill(FillMode::color(STOP_COLOR)),
+            Transform::from_xyz(0.0, 0.0, 1.0),
+        ));
+    }
+}
@@ -0,0 +1,241 @@
+//! Buses: vehicles driving along a transit line instead of random routes, standing at its stops
+//! and leaving the road at its end.
+
+use super::{route::LanePath, Vehicle};
+use crate::{
+    ai::Actions, config::TransitConfig, person::Walker, road::RoadGraph, simulation::SimClock,
+    transit::TransitLine,
+};
+use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Distance from a stop within which a bus that stopped stands at it.
+const STOP_TOLERANCE: f32 = 1.0;
+
+/// Someone riding a bus. People are despawned when they get on, and spawned again as they were
+/// when they get off.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Passenger {
+    /// Stop the passenger gets off at.
+    pub alight: Vec2,
+    pub walker: Option<Walker>,
+    pub trip_start: Option<f32>,
+    pub target: Vec2,
+    pub target_door: Option<Vec2>,
+    /// Plan of the passenger, the ride being its current step.
+    pub actions: Actions,
+}
+
+#[derive(Component)]
+pub struct Bus {
+    pub line: Entity,
+    /// Stops of the line and their distance along the path of the bus.
+    pub(crate) stops: Vec<(Vec2, f32)>,
+    /// Index of the next stop, or of the current one while standing there.
+    pub(crate) next_stop: usize,
+    /// Seconds spent standing at the current stop, while the bus does.
+    pub(crate) dwell: Option<f32>,
+    /// Whether the bus reached the end of the line.
+    pub(crate) finished: bool,
+    pub passengers: Vec<Passenger>,
+}
+
+impl Bus {
+    /// The stop the bus stands at, if it does.
+    pub fn standing_at(&self) -> Option<Vec2> {
+        self.dwell
+            .and(self.stops.get(self.next_stop))
+            .map(|&(stop, _)| stop)
+    }
+
+    /// Whether the bus still stops at `stop` after the current stop.
+    pub fn goes_to(&self, stop: Vec2) -> bool {
+        let after = self.next_stop + usize::from(self.dwell.is_some());
+        self.stops
+            .iter()
+            .skip(after)
+            .any(|&(other, _)| other.distance(stop) < 0.1)
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Distance along the path of the bus at which it stops next.
+    pub(super) fn next_stop_distance(&self) -> Option<f32> {
+        self.stops
+            .get(self.next_stop)
+            .map(|&(_, distance)| distance)
+    }
+
+    pub(super) fn is_standing(&self) -> bool {
+        self.dwell.is_some()
+    }
+}
+
+/// The path buses of `line` drive along, keeping to the lane on the right, and the stops they
+/// stand at with their distance along it, unless the streets of the line are not connected.
+pub fn line_route(graph: &RoadGraph, line: &TransitLine) -> Option<(LanePath, Vec<(Vec2, f32)>)> {
+    let connected = line
+        .nodes
+        .windows(2)
+        .all(|pair| graph.neighbours(pair[0]).contains(&pair[1]));
+    if !connected {
+        return None;
+    }
+    let path = LanePath::new(graph.lane_points(&line.nodes, 0));
+    if path.length() <= 0.0 {
+        return None;
+    }
+    let stops = served_stops(&path, &line.stops);
+    Some((path, stops))
+}
+
+/// The stops a bus driving along `path` stands at and their distance along it. Stops projecting
+/// onto the path more than `STOP_TOLERANCE` behind the previous one are already passed, and
+/// skipped.
+fn served_stops(path: &LanePath, stops: &[Vec2]) -> Vec<(Vec2, f32)> {
+    let mut reached = 0.0;
+    stops
+        .iter()
+        .filter_map(|&stop| {
+            let distance = path.project(stop);
+            if distance + STOP_TOLERANCE < reached {
+                return None;
+            }
+            reached = distance;
+            Some((stop, distance))
+        })
+        .collect()
+}
+
+/// Spawns a bus at the start of the line, keeping to the lane on the right, unless the streets of
+/// the line are not connected.
+pub fn spawn_bus(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    config: &TransitConfig,
+    graph: &RoadGraph,
+    line_entity: Entity,
+    line: &TransitLine,
+) -> Option<Entity> {
+    let destination = *line.nodes.last()?;
+    let (path, stops) = line_route(graph, line)?;
+    let vehicle = Vehicle {
+        speed: 0.0,
+        destination,
+        lane: 0,
+        path,
+        distance: 0.0,
+    };
+    let bus = Bus {
+        line: line_entity,
+        stops,
+        next_stop: 0,
+        dwell: None,
+        finished: false,
+        passengers: vec![],
+    };
+    Some(add_bus(commands, meshes, materials, config, vehicle, bus))
+}
+
+/// Spawns a bus where `vehicle` is along its path.
+pub fn add_bus(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    config: &TransitConfig,
+    vehicle: Vehicle,
+    bus: Bus,
+) -> Entity {
+    let (pos, dir) = vehicle.path.sample(vehicle.distance);
+    commands
+        .spawn()
+        .insert(vehicle)
+        .insert(bus)
+        .insert(RigidBody::KinematicPositionBased)
+        .insert(Collider::cuboid(
+            config.bus_length / 2.0,
+            config.bus_width / 2.0,
+        ))
+        .insert_bundle(MaterialMesh2dBundle {
+            mesh: meshes
+                .add(Mesh::from(shape::Quad::new(Vec2::new(
+                    config.bus_length,
+                    config.bus_width,
+                ))))
+                .into(),
+            material: materials.add(ColorMaterial::from(Color::ORANGE)),
+            transform: Transform::from_xyz(pos.x, pos.y, 15.0)
+                .with_rotation(Quat::from_rotation_z(dir.y.atan2(dir.x))),
+            ..default()
+        })
+        .id()
+}
+
+/// Stops the buses at their stops for the dwell time, and takes them off the road once they
+/// reached the end of their line and everyone got off.
+pub fn serve_stops(
+    mut commands: Commands,
+    config: Res<TransitConfig>,
+    clock: Res<SimClock>,
+    mut buses: Query<(Entity, &mut Vehicle, &mut Bus)>,
+) {
+    let dt = clock.delta().as_secs_f32();
+    for (entity, mut vehicle, mut bus) in buses.iter_mut() {
+        if let Some(dwell) = bus.dwell {
+            vehicle.speed = 0.0;
+            if dwell + dt >= config.dwell_time {
+                bus.dwell = None;
+                bus.next_stop += 1;
+            } else {
+                bus.dwell = Some(dwell + dt);
+            }
+            continue;
+        }
+        match bus.next_stop_distance() {
+            Some(stop) if vehicle.distance > stop + STOP_TOLERANCE => {
+                // Stops the lane does not pass by are skipped.
+                bus.next_stop += 1;
+            }
+            Some(stop) if stop - vehicle.distance < STOP_TOLERANCE && vehicle.speed < 0.5 => {
+                vehicle.speed = 0.0;
+                bus.dwell = Some(0.0);
+            }
+            Some(_) => {}
+            None if bus.finished && bus.passengers.is_empty() => {
+                commands.entity(entity).despawn();
+            }
+            None => bus.finished = vehicle.distance >= vehicle.path.length(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_behind_the_bus_are_skipped() {
+        let path = LanePath::new(vec![(Vec2::ZERO, 10.0), (Vec2::new(100.0, 0.0), 10.0)]);
+        let stops = [
+            Vec2::new(20.0, 5.0),
+            Vec2::new(60.0, 5.0),
+            Vec2::new(40.0, 5.0),
+            Vec2::new(60.5, -5.0),
+            Vec2::new(90.0, 5.0),
+        ];
+        let served = served_stops(&path, &stops);
+        let positions: Vec<Vec2> = served.iter().map(|&(stop, _)| stop).collect();
+        assert_eq!(
+            positions,
+            [stops[0], stops[1], stops[3], stops[4]],
+            "the stop at 40 is passed on the way to the one at 60"
+        );
+        for (stop, distance) in served {
+            assert!((distance - stop.x).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Intelligent driver model: vehicles accelerate towards their desired speed and brake to keep a
+//! safe, speed dependent gap to whatever is ahead of them.
+
+use crate::config::IdmConfig;
+
+/// Whatever a vehicle follows: another vehicle, or a person or a stop line, which do not move.
+#[derive(Clone, Copy, Debug)]
+pub struct Leader {
+    /// Distance from the front of the vehicle to the back of the leader.
+    pub gap: f32,
+    pub speed: f32,
+}
+
+/// Returns the acceleration of a vehicle at `speed` that would like to drive at `desired_speed`,
+/// behind `leader` if there is one.
+pub fn acceleration(
+    speed: f32,
+    desired_speed: f32,
+    leader: Option<Leader>,
+    config: &IdmConfig,
+) -> f32 {
+    let free_road = 1.0 - (speed / desired_speed.max(0.1)).powf(config.accel_exponent);
+    let interaction = match leader {
+        Some(leader) => {
+            let approach_rate = speed - leader.speed;
+            let desired_gap = config.min_gap
+                + (speed * config.time_headway
+                    + speed * approach_rate
+                        / (2.0 * (config.max_accel * config.comfortable_decel).sqrt()))
+                .max(0.0);
+            (desired_gap / leader.gap.max(0.1)).powi(2)
+        }
+        None => 0.0,
+    };
+    config.max_accel * (free_road - interaction)
+}
+
+/// Distance a vehicle at `speed` needs to stop braking comfortably.
+pub fn stopping_distance(speed: f32, config: &IdmConfig) -> f32 {
+    speed * speed / (2.0 * config.comfortable_decel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_acceleration_at_desired_speed_on_free_road() {
+        let config = IdmConfig::default();
+        assert!(acceleration(20.0, 20.0, None, &config).abs() < 1e-4);
+        assert_eq!(acceleration(0.0, 20.0, None, &config), config.max_accel);
+    }
+
+    #[test]
+    fn brakes_behind_stopped_leader() {
+        let config = IdmConfig::default();
+        let leader = Leader {
+            gap: 10.0,
+            speed: 0.0,
+        };
+        assert!(acceleration(15.0, 20.0, Some(leader), &config) < -config.comfortable_decel);
+
+        // Stopped at the minimum gap, the vehicle stays there.
+        let leader = Leader {
+            gap: config.min_gap,
+            speed: 0.0,
+        };
+        assert!(acceleration(0.0, 20.0, Some(leader), &config).abs() < 1e-4);
+    }
+}
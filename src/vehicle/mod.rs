@@ -0,0 +1,398 @@
+pub mod bus;
+pub mod idm;
+pub mod route;
+
+use crate::{
+    ai::CrowdAiLabel,
+    city::CityLabel,
+    config::{CrowdConfig, VehicleConfig},
+    person::{Person, PersonLabel, PersonState},
+    player::Player,
+    rng::SimRng,
+    road::RoadGraph,
+    signal::{SignalLabel, StopLine, StopLines},
+    simulation::SimClock,
+    spatial_hash::SpatialHash,
+};
+use bevy::{math::Vec3Swizzles, prelude::*, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use bus::Bus;
+use idm::Leader;
+use rand::{seq::SliceRandom, Rng};
+use route::LanePath;
+
+/// Cars following the intelligent driver model along the lanes of the roads, between random
+/// nodes of the road graph.
+pub struct VehiclePlugin;
+
+#[derive(SystemLabel)]
+pub enum VehicleLabel {
+    Drive,
+}
+
+impl Plugin for VehiclePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VehicleConfig>()
+            .init_resource::<CrowdConfig>()
+            .init_resource::<RoadGraph>()
+            .init_resource::<SpatialHash>()
+            .init_resource::<StopLines>()
+            .add_system(spawn_vehicles.after(CityLabel::RoadGraph))
+            .add_system(
+                drive
+                    .label(VehicleLabel::Drive)
+                    .after(CityLabel::RoadGraph)
+                    .after(SignalLabel::RoadAreas)
+                    .after(PersonLabel::SpatialHash),
+            )
+            .add_system(
+                yield_to_vehicles
+                    .after(CrowdAiLabel::Formation)
+                    .before(PersonLabel::Movement),
+            );
+    }
+}
+
+#[derive(Component)]
+pub struct Vehicle {
+    pub speed: f32,
+    /// Node the vehicle is driving to.
+    pub(crate) destination: Entity,
+    /// Lane the vehicle keeps to, counted from the right kerb.
+    pub(crate) lane: u32,
+    pub(crate) path: LanePath,
+    /// Distance driven along the path.
+    pub(crate) distance: f32,
+}
+
+impl Vehicle {
+    /// Direction the vehicle is driving in.
+    pub fn dir(&self) -> Vec2 {
+        self.path.sample(self.distance).1
+    }
+}
+
+/// Picks a random destination from `from` and returns it with the lane leading there, starting
+/// at `start` if given.
+fn random_route(
+    graph: &RoadGraph,
+    rng: &mut SimRng,
+    from: Entity,
+    lane: u32,
+    start: Option<Vec2>,
+) -> Option<(Entity, LanePath)> {
+    let nodes = graph.connected_nodes();
+    let destination = *nodes
+        .iter()
+        .filter(|&&node| node != from)
+        .collect::<Vec<_>>()
+        .choose(&mut **rng)?;
+    let route = graph.shortest_route(from, *destination)?;
+    let mut points = graph.lane_points(&route, lane);
+    if let (Some(start), Some(&(_, speed_limit))) = (start, points.first()) {
+        points.insert(0, (start, speed_limit));
+    }
+    Some((*destination, LanePath::new(points)))
+}
+
+/// Keeps `count` cars on the roads, adding at most one per frame where there is room for it.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_vehicles(
+    mut commands: Commands,
+    config: Res<VehicleConfig>,
+    graph: Res<RoadGraph>,
+    mut rng: ResMut<SimRng>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    vehicles: Query<&Transform, With<Vehicle>>,
+    cars: Query<(), (With<Vehicle>, Without<Bus>)>,
+) {
+    if cars.iter().count() >= config.count {
+        return;
+    }
+    let from = match graph.connected_nodes().choose(&mut **rng) {
+        Some(&from) => from,
+        None => return,
+    };
+    let lanes = graph
+        .neighbours(from)
+        .iter()
+        .filter_map(|&to| graph.road(from, to))
+        .map(|road| road.lanes_per_direction())
+        .max()
+        .unwrap_or(1);
+    let lane = rng.gen_range(0..lanes);
+    let (destination, path) = match random_route(&graph, &mut rng, from, lane, None) {
+        Some(route) => route,
+        None => return,
+    };
+    let (pos, _) = path.sample(0.0);
+    if vehicles
+        .iter()
+        .any(|transform| transform.translation.xy().distance(pos) < 2.0 * config.length)
+    {
+        return;
+    }
+
+    add_vehicle(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        &config,
+        Vehicle {
+            speed: 0.0,
+            destination,
+            lane,
+            path,
+            distance: 0.0,
+        },
+    );
+}
+
+/// Spawns a car at its distance along its path.
+pub fn add_vehicle(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    config: &VehicleConfig,
+    vehicle: Vehicle,
+) -> Entity {
+    let (pos, dir) = vehicle.path.sample(vehicle.distance);
+    commands
+        .spawn()
+        .insert(vehicle)
+        .insert(RigidBody::KinematicPositionBased)
+        .insert(Collider::cuboid(config.length / 2.0, config.width / 2.0))
+        .insert_bundle(MaterialMesh2dBundle {
+            mesh: meshes
+                .add(Mesh::from(shape::Quad::new(Vec2::new(
+                    config.length,
+                    config.width,
+                ))))
+                .into(),
+            material: materials.add(ColorMaterial::from(Color::MIDNIGHT_BLUE)),
+            transform: Transform::from_xyz(pos.x, pos.y, 15.0)
+                .with_rotation(Quat::from_rotation_z(dir.y.atan2(dir.x))),
+            ..default()
+        })
+        .id()
+}
+
+/// Returns the closest vehicle, person or red light stop line in the lane ahead, within the
+/// lookahead distance.
+#[allow(clippy::too_many_arguments)]
+fn leader(
+    entity: Entity,
+    pos: Vec2,
+    dir: Vec2,
+    others: &[(Entity, Vec2, Vec2, f32)],
+    stop_lines: &[StopLine],
+    spatial_hash: &SpatialHash,
+    config: &VehicleConfig,
+    crowd_config: &CrowdConfig,
+) -> Option<Leader> {
+    let vehicles = others
+        .iter()
+        .filter(|(other, ..)| *other != entity)
+        .filter_map(|&(_, other_pos, other_dir, other_speed)| {
+            let offset = other_pos - pos;
+            let along = offset.dot(dir);
+            let in_lane = offset.perp_dot(dir).abs() < config.width && dir.dot(other_dir) > 0.5;
+            (along > 0.0 && along < config.lookahead_distance && in_lane).then_some(Leader {
+                gap: along - config.length,
+                speed: other_speed * dir.dot(other_dir),
+            })
+        });
+
+    // Vehicles brake for people in their lane, who in turn wait for the vehicles that could not.
+    let half_lookahead = config.lookahead_distance / 2.0;
+    let people = spatial_hash
+        .within_radius(pos + half_lookahead * dir, half_lookahead)
+        .filter_map(|person| {
+            let offset = person.pos - pos;
+            let along = offset.dot(dir);
+            let in_lane = offset.perp_dot(dir).abs() < config.width / 2.0 + crowd_config.half_size;
+            (along > 0.0 && in_lane).then_some(Leader {
+                gap: along - config.length / 2.0 - crowd_config.half_size,
+                speed: 0.0,
+            })
+        });
+
+    // A vehicle already past the stop line when the light turns red drives on.
+    let front = pos + config.length / 2.0 * dir;
+    let red_lights = stop_lines.iter().filter_map(|stop_line| {
+        let offset = stop_line.pos - front;
+        let along = offset.dot(dir);
+        let on_road = offset.perp_dot(stop_line.dir).abs() < stop_line.half_width;
+        (along > 0.0
+            && along < config.lookahead_distance
+            && on_road
+            && dir.dot(stop_line.dir) > 0.7)
+            .then_some(Leader {
+                gap: along,
+                speed: 0.0,
+            })
+    });
+
+    vehicles
+        .chain(people)
+        .chain(red_lights)
+        .min_by(|a, b| a.gap.total_cmp(&b.gap))
+}
+
+/// Desired speed within the speed limit, that lets the vehicle slow down comfortably to the turn
+/// speed before every turn ahead, to the speed it can take every curve at and to every lower
+/// speed limit.
+fn desired_speed(vehicle: &Vehicle, config: &VehicleConfig) -> f32 {
+    let path = &vehicle.path;
+    let turns = path
+        .turns_after(vehicle.distance)
+        .map(|(distance, _)| (distance, config.turn_speed));
+    let curves = path
+        .curves_after(vehicle.distance)
+        .map(|(distance, radius)| (distance, (config.lateral_accel * radius).sqrt()));
+    path.speed_limits_after(vehicle.distance)
+        .chain(turns)
+        .chain(curves)
+        .filter(|&(distance, _)| distance - vehicle.distance < config.lookahead_distance)
+        .map(|(distance, speed)| {
+            (speed.powi(2) + 2.0 * config.idm.comfortable_decel * (distance - vehicle.distance))
+                .sqrt()
+        })
+        .fold(
+            config
+                .idm
+                .desired_speed
+                .min(path.speed_limit(vehicle.distance)),
+            f32::min,
+        )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn drive(
+    mut commands: Commands,
+    config: Res<VehicleConfig>,
+    crowd_config: Res<CrowdConfig>,
+    clock: Res<SimClock>,
+    graph: Res<RoadGraph>,
+    spatial_hash: Res<SpatialHash>,
+    stop_lines: Res<StopLines>,
+    mut rng: ResMut<SimRng>,
+    mut vehicles: Query<(Entity, &mut Vehicle, &mut Transform, Option<&Bus>)>,
+) {
+    let dt = clock.delta().as_secs_f32();
+    let others: Vec<_> = vehicles
+        .iter()
+        .map(|(entity, vehicle, transform, _)| {
+            (
+                entity,
+                transform.translation.xy(),
+                vehicle.dir(),
+                vehicle.speed,
+            )
+        })
+        .collect();
+
+    for (entity, mut vehicle, mut transform, bus) in vehicles.iter_mut() {
+        if bus.is_some_and(|bus| bus.is_standing()) {
+            continue;
+        }
+        let pos = transform.translation.xy();
+        let dir = vehicle.dir();
+        // Buses pull up at their next stop as if it was a stopped vehicle.
+        let next_stop = bus
+            .and_then(|bus| bus.next_stop_distance())
+            .map(|stop| Leader {
+                gap: stop - vehicle.distance + config.idm.min_gap,
+                speed: 0.0,
+            });
+        let leader = leader(
+            entity,
+            pos,
+            dir,
+            &others,
+            &stop_lines,
+            &spatial_hash,
+            &config,
+            &crowd_config,
+        )
+        .into_iter()
+        .chain(next_stop)
+        .min_by(|a, b| a.gap.total_cmp(&b.gap));
+        let desired_speed = desired_speed(&vehicle, &config);
+        let acceleration = idm::acceleration(vehicle.speed, desired_speed, leader, &config.idm)
+            .max(-4.0 * config.idm.comfortable_decel);
+        vehicle.speed = (vehicle.speed + acceleration * dt).max(0.0);
+        vehicle.distance += vehicle.speed * dt;
+
+        if bus.is_some() && vehicle.distance >= vehicle.path.length() {
+            // At the end of its line, until everyone got off.
+            vehicle.distance = vehicle.path.length();
+            vehicle.speed = 0.0;
+        } else if vehicle.distance >= vehicle.path.length() {
+            let end = vehicle.path.end().unwrap_or(pos);
+            match random_route(
+                &graph,
+                &mut rng,
+                vehicle.destination,
+                vehicle.lane,
+                Some(end),
+            ) {
+                Some((destination, path)) => {
+                    vehicle.distance -= vehicle.path.length();
+                    vehicle.destination = destination;
+                    vehicle.path = path;
+                }
+                None => {
+                    // The road network changed under the vehicle.
+                    commands.entity(entity).despawn();
+                    continue;
+                }
+            }
+        }
+
+        let (pos, dir) = vehicle.path.sample(vehicle.distance);
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+        transform.rotation = Quat::from_rotation_z(dir.y.atan2(dir.x));
+    }
+}
+
+/// Stops people about to step in front of a vehicle that could not brake comfortably for them.
+pub fn yield_to_vehicles(
+    config: Res<VehicleConfig>,
+    crowd_config: Res<CrowdConfig>,
+    vehicles: Query<(&Vehicle, &Transform)>,
+    mut people: Query<(&mut Person, &Transform), Without<Player>>,
+) {
+    let in_front = |vehicle: &Vehicle, vehicle_pos: Vec2, point: Vec2| {
+        let dir = vehicle.dir();
+        let offset = point - vehicle_pos;
+        let along = offset.dot(dir);
+        let reach = config.length / 2.0
+            + config.idm.min_gap
+            + idm::stopping_distance(vehicle.speed, &config.idm);
+        along > -config.length / 2.0
+            && along < reach
+            && offset.perp_dot(dir).abs() < config.width / 2.0 + config.yield_margin
+    };
+
+    for (mut person, transform) in people.iter_mut() {
+        let walk_dir = match person.state {
+            PersonState::Walking(velocity) => velocity.normalize_or_zero(),
+            PersonState::Standing => continue,
+        };
+        let pos = transform.translation.xy();
+        let next_pos = pos + (crowd_config.half_size + config.yield_margin) * walk_dir;
+        let must_yield = vehicles.iter().any(|(vehicle, vehicle_transform)| {
+            let vehicle_pos = vehicle_transform.translation.xy();
+            // Someone already in front of the vehicle better keep going.
+            vehicle.speed > 0.1
+                && in_front(vehicle, vehicle_pos, next_pos)
+                && !in_front(vehicle, vehicle_pos, pos)
+        });
+        if must_yield {
+            person.state = PersonState::Standing;
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! The lane vehicles keep to along a route of the road graph.
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Turns sharper than this, in radians, make vehicles slow down to the turn speed. Gentler ones
+/// are part of a curve, which vehicles take at a speed depending on its radius.
+const MIN_TURN_ANGLE: f32 = 0.3;
+
+/// Polyline a vehicle drives along, parametrised by the distance from its start.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LanePath {
+    points: Vec<Vec2>,
+    /// Distance from the start to every point.
+    distances: Vec<f32>,
+    /// Speed limit from every point to the next.
+    speed_limits: Vec<f32>,
+}
+
+impl LanePath {
+    /// Builds the path through `points`, each with the speed limit of the segment starting there.
+    pub fn new(points: Vec<(Vec2, f32)>) -> Self {
+        let mut path = Self {
+            points: Vec::with_capacity(points.len()),
+            distances: Vec::with_capacity(points.len()),
+            speed_limits: Vec::with_capacity(points.len()),
+        };
+        for (point, speed_limit) in points {
+            match path.points.last() {
+                Some(&last) if last.distance(point) < 0.01 => {
+                    *path.speed_limits.last_mut().unwrap() = speed_limit;
+                }
+                Some(&last) => {
+                    path.distances
+                        .push(path.distances.last().unwrap() + last.distance(point));
+                    path.points.push(point);
+                    path.speed_limits.push(speed_limit);
+                }
+                None => {
+                    path.distances.push(0.0);
+                    path.points.push(point);
+                    path.speed_limits.push(speed_limit);
+                }
+            }
+        }
+        path
+    }
+
+    pub fn length(&self) -> f32 {
+        self.distances.last().copied().unwrap_or(0.0)
+    }
+
+    pub fn end(&self) -> Option<Vec2> {
+        self.points.last().copied()
+    }
+
+    /// Returns the position and direction at `distance` along the path.
+    pub fn sample(&self, distance: f32) -> (Vec2, Vec2) {
+        if self.points.len() < 2 {
+            return (self.points.first().copied().unwrap_or_default(), Vec2::X);
+        }
+        let segment = self.segment(distance);
+        let (from, to) = (self.points[segment], self.points[segment + 1]);
+        let dir = (to - from).normalize();
+        (from + (distance - self.distances[segment]) * dir, dir)
+    }
+
+    /// Returns the distance along the path of the point of the path closest to `point`.
+    pub fn project(&self, point: Vec2) -> f32 {
+        (0..self.points.len().saturating_sub(1))
+            .map(|segment| {
+                let (from, to) = (self.points[segment], self.points[segment + 1]);
+                let along = (point - from)
+                    .dot((to - from).normalize())
+                    .clamp(0.0, from.distance(to));
+                let closest = from + along * (to - from).normalize();
+                (closest.distance(point), self.distances[segment] + along)
+            })
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .map_or(0.0, |(_, distance)| distance)
+    }
+
+    pub fn speed_limit(&self, distance: f32) -> f32 {
+        if self.points.len() < 2 {
+            return self.speed_limits.first().copied().unwrap_or(f32::INFINITY);
+        }
+        self.speed_limits[self.segment(distance)]
+    }
+
+    /// Returns the distance along the path and the speed limit of the segments starting after
+    /// `distance`.
+    pub fn speed_limits_after(&self, distance: f32) -> impl Iterator<Item = (f32, f32)> + '_ {
+        (1..self.points.len().saturating_sub(1))
+            .filter(move |&index| self.distances[index] > distance)
+            .map(|index| (self.distances[index], self.speed_limits[index]))
+    }
+
+    /// Returns the distance along the path and the angle of the turns after `distance`.
+    pub fn turns_after(&self, distance: f32) -> impl Iterator<Item = (f32, f32)> + '_ {
+        (1..self.points.len().saturating_sub(1))
+            .filter(move |&index| self.distances[index] > distance)
+            .map(|index| {
+                let dir = self.points[index] - self.points[index - 1];
+                let next_dir = self.points[index + 1] - self.points[index];
+                (self.distances[index], dir.angle_between(next_dir).abs())
+            })
+            .filter(|&(_, angle)| angle > MIN_TURN_ANGLE)
+    }
+
+    /// Returns the distance along the path and the radius of the curves after `distance`, as
+    /// estimated from the angle between the segments around every point.
+    pub fn curves_after(&self, distance: f32) -> impl Iterator<Item = (f32, f32)> + '_ {
+        (1..self.points.len().saturating_sub(1))
+            .filter(move |&index| self.distances[index] > distance)
+            .filter_map(|index| {
+                let dir = self.points[index] - self.points[index - 1];
+                let next_dir = self.points[index + 1] - self.points[index];
+                let angle = dir.angle_between(next_dir).abs();
+                let arc_length = (self.distances[index + 1] - self.distances[index - 1]) / 2.0;
+                (angle > 0.01 && angle <= MIN_TURN_ANGLE)
+                    .then_some((self.distances[index], arc_length / angle))
+            })
+    }
+
+    /// Index of the segment at `distance`, for a path of two points or more.
+    fn segment(&self, distance: f32) -> usize {
+        self.distances
+            .iter()
+            .skip(1)
+            .position(|&end| end >= distance)
+            .unwrap_or(self.points.len() - 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bent_path() -> LanePath {
+        LanePath::new(vec![
+            (Vec2::ZERO, 10.0),
+            (Vec2::new(10.0, 0.0), 5.0),
+            (Vec2::new(10.0, 10.0), 5.0),
+        ])
+    }
+
+    #[test]
+    fn sample_follows_the_bend() {
+        let path = bent_path();
+        assert_eq!(path.length(), 20.0);
+        assert_eq!(path.sample(5.0), (Vec2::new(5.0, 0.0), Vec2::X));
+        assert_eq!(path.sample(15.0), (Vec2::new(10.0, 5.0), Vec2::Y));
+        assert_eq!(path.speed_limit(5.0), 10.0);
+        assert_eq!(path.speed_limit(15.0), 5.0);
+    }
+
+    #[test]
+    fn project_undoes_sample() {
+        let path = bent_path();
+        for step in 0..=40 {
+            let distance = step as f32 * 0.5;
+            let (pos, _) = path.sample(distance);
+            assert!((path.project(pos) - distance).abs() < 1e-4);
+        }
+        // Off the path, the closest point counts.
+        assert!((path.project(Vec2::new(12.0, 5.0)) - 15.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,56 @@
+//! Two headless runs from the same seed have to end in the same state, to the bit.
+
+use bevy::{prelude::*, utils::Duration};
+use bevy_jam_2::{
+    headless::{self, HeadlessDuration},
+    metrics::SimMetrics,
+    person::Person,
+    *,
+};
+
+const SEED: u64 = 7;
+const TICKS: u32 = 30 * 60;
+
+/// Runs the simulation for `TICKS` steps, returning the metrics and the positions of the people.
+fn run(seed: u64) -> (String, Vec<Vec2>) {
+    let mut app = App::new();
+    headless::add_headless_plugins(&mut app, HeadlessDuration(f32::INFINITY));
+    app.add_plugin(SimulationPlugin { seed })
+        .add_plugin(CityPlugin)
+        .add_plugin(PersonPlugin)
+        .add_plugin(CrowdAiPlugin)
+        .add_plugin(SpawningPlugin)
+        .add_plugin(EvacuationPlugin)
+        .add_plugin(SignalPlugin)
+        .add_plugin(VehiclePlugin)
+        .add_plugin(TransitPlugin);
+    for _ in 0..TICKS {
+        app.update();
+    }
+
+    let mut metrics = std::mem::take(&mut *app.world.resource_mut::<SimMetrics>());
+    // Measured in wall clock time.
+    metrics.pathfinding_time = Duration::ZERO;
+    let positions = app
+        .world
+        .query_filtered::<&Transform, With<Person>>()
+        .iter(&app.world)
+        .map(|transform| transform.translation.truncate())
+        .collect();
+    (format!("{:?}", metrics), positions)
+}
+
+#[test]
+fn same_seed_same_run() {
+    let (metrics, positions) = run(SEED);
+    let (other_metrics, other_positions) = run(SEED);
+    assert!(!positions.is_empty());
+    assert_eq!(metrics, other_metrics);
+    assert_eq!(positions.len(), other_positions.len());
+    for (pos, other) in positions.iter().zip(&other_positions) {
+        assert_eq!(
+            pos.to_array().map(f32::to_bits),
+            other.to_array().map(f32::to_bits)
+        );
+    }
+}